@@ -2,11 +2,14 @@ use std::io;
 use std::marker::PhantomData;
 
 pub use http;
-use lunatic::net::{TcpListener, ToSocketAddrs};
-use lunatic::Process;
+use lunatic::function::FuncRef;
+use lunatic::net::{TcpListener, TlsListener, ToSocketAddrs};
+use lunatic::ProcessConfig;
 
-use crate::supervisor::request_supervisor;
-use crate::ProcessSafeHandler;
+use crate::failure::ErrorHandler;
+use crate::response::Response;
+use crate::routes::RouteInfo;
+use crate::{server, Failure, ProcessSafeHandler, ServerConfig, ShutdownHandle};
 
 /// An application containing a router for listening and handling incoming
 /// requests.
@@ -23,9 +26,14 @@ use crate::ProcessSafeHandler;
 /// })
 /// .serve("0.0.0.0:3000")
 /// ```
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Application<T, Kind, Arg, Ret> {
     handler: T,
+    config: ServerConfig,
+    worker_config: Option<ProcessConfig>,
+    error_handler: Option<ErrorHandler>,
+    shutdown: Option<ShutdownHandle>,
+    log_routes: bool,
     phantom: PhantomData<(Kind, Arg, Ret)>,
 }
 
@@ -35,34 +43,207 @@ where
 {
     /// Creates a new application with a given router.
     pub fn new(handler: fn() -> T) -> Self {
+        let handler = handler();
         Application {
-            handler: handler(),
+            handler,
+            config: ServerConfig::default(),
+            worker_config: None,
+            error_handler: None,
+            shutdown: None,
+            log_routes: false,
             phantom: PhantomData,
         }
     }
 
+    /// Returns the routes of the router, if it was created with the
+    /// [`router!`](crate::router) macro.
+    pub fn routes(&self) -> &'static [RouteInfo] {
+        self.handler.routes()
+    }
+
+    /// Sets the limits and timeouts used when handling connections.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the process configuration of the worker processes handling
+    /// requests, limiting their memory, fuel and capabilities.
+    ///
+    /// Requests whose worker exceeds the limits are answered with
+    /// `503 Service Unavailable`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lunatic::ProcessConfig;
+    /// use submillisecond::{router, Application};
+    ///
+    /// fn index() -> &'static str { "Welcome" }
+    ///
+    /// let mut worker_config = ProcessConfig::new().unwrap();
+    /// worker_config.set_max_memory(16 * 1024 * 1024);
+    /// worker_config.set_max_fuel(10_000);
+    /// worker_config.set_can_spawn_processes(true);
+    /// Application::new(router! {
+    ///     GET "/" => index
+    /// })
+    /// .with_worker_config(worker_config)
+    /// .serve("0.0.0.0:3000")
+    /// ```
+    pub fn with_worker_config(mut self, worker_config: ProcessConfig) -> Self {
+        self.worker_config = Some(worker_config);
+        self
+    }
+
+    /// Sets the function rendering the responses to failures the server
+    /// answers itself, such as invalid requests, timeouts, panicking handlers
+    /// and rejected connections.
+    ///
+    /// The error handler runs in its own process. If it panics, the default
+    /// plain text response is sent instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use submillisecond::http::header;
+    /// use submillisecond::response::{IntoResponse, Response};
+    /// use submillisecond::{router, Application, Failure};
+    ///
+    /// fn index() -> &'static str { "Welcome" }
+    ///
+    /// fn error_handler(failure: Failure) -> Response {
+    ///     let body = format!(
+    ///         "<h1>{}</h1><p>{} {}</p>",
+    ///         failure.status(),
+    ///         failure.method().unwrap_or("-"),
+    ///         failure.path().unwrap_or("-"),
+    ///     );
+    ///     (failure.status(), [(header::CONTENT_TYPE, "text/html")], body).into_response()
+    /// }
+    ///
+    /// Application::new(router! {
+    ///     GET "/" => index
+    /// })
+    /// .with_error_handler(error_handler)
+    /// .serve("0.0.0.0:3000")
+    /// ```
+    pub fn with_error_handler(mut self, error_handler: fn(Failure) -> Response) -> Self {
+        self.error_handler = Some(FuncRef::new(error_handler));
+        self
+    }
+
+    /// Logs the routes of the router when the server starts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use submillisecond::{router, Application};
+    ///
+    /// fn index() -> &'static str { "Welcome" }
+    ///
+    /// Application::new(router! {
+    ///     GET "/" => index
+    /// })
+    /// .with_route_logging()
+    /// .serve("0.0.0.0:3000")
+    /// ```
+    pub fn with_route_logging(mut self) -> Self {
+        self.log_routes = true;
+        self
+    }
+
+    /// Allows shutting down the server with the `shutdown` handle, making
+    /// [`serve`](Application::serve) return once the active connections are
+    /// finished.
+    ///
+    /// # Panics
+    ///
+    /// Serving panics if the handle was created in another process.
+    pub fn with_shutdown(mut self, shutdown: ShutdownHandle) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
     /// Listen on `addr` to receive incoming requests, and handling them with
     /// the router.
+    ///
+    /// Returns after the server is shut down, see
+    /// [`with_shutdown`](Application::with_shutdown).
     pub fn serve<A>(self, addr: A) -> io::Result<()>
     where
         A: ToSocketAddrs + Clone,
     {
+        let routes = self.handler.routes();
         let safe_handler = self.handler.safe_handler();
         let listener = TcpListener::bind(addr.clone())?;
         match listener.local_addr() {
-            Ok(a) => log_server_start(a),
-            Err(_) => log_server_start(addr),
+            Ok(a) => log_server_start(a, "http"),
+            Err(_) => log_server_start(addr, "http"),
+        }
+        if self.log_routes {
+            log_routes(routes);
+        }
+        server::serve::<_, _, Arg, Ret>(
+            listener,
+            safe_handler,
+            self.config,
+            self.worker_config,
+            self.error_handler,
+            self.shutdown,
+        );
+
+        Ok(())
+    }
+
+    /// Listen on `addr` to receive incoming HTTPS requests, and handling them
+    /// with the router.
+    ///
+    /// The certificate chain and private key are expected in PEM format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use submillisecond::{router, Application};
+    ///
+    /// fn index() -> &'static str { "Welcome" }
+    ///
+    /// let cert = std::fs::read_to_string("cert.pem").unwrap();
+    /// let key = std::fs::read_to_string("key.pem").unwrap();
+    /// Application::new(router! {
+    ///     GET "/" => index
+    /// })
+    /// .serve_tls("0.0.0.0:3443", &cert, &key)
+    /// ```
+    pub fn serve_tls<A>(self, addr: A, cert_pem: &str, key_pem: &str) -> io::Result<()>
+    where
+        A: ToSocketAddrs + Clone,
+    {
+        let routes = self.handler.routes();
+        let safe_handler = self.handler.safe_handler();
+        let listener = TlsListener::bind(addr.clone(), cert_pem.to_string(), key_pem.to_string())?;
+        match listener.local_addr() {
+            Ok(a) => log_server_start(a, "https"),
+            Err(_) => log_server_start(addr, "https"),
         }
-        while let Ok((stream, _)) = listener.accept() {
-            Process::spawn_link((stream, safe_handler.clone()), request_supervisor);
+        if self.log_routes {
+            log_routes(routes);
         }
+        server::serve::<_, _, Arg, Ret>(
+            listener,
+            safe_handler,
+            self.config,
+            self.worker_config,
+            self.error_handler,
+            self.shutdown,
+        );
 
         Ok(())
     }
 }
 
 #[cfg(feature = "logging")]
-fn log_server_start<A: ToSocketAddrs>(addr: A) {
+fn log_server_start<A: ToSocketAddrs>(addr: A, scheme: &str) {
     use lunatic_log::subscriber::fmt::FmtSubscriber;
     use lunatic_log::{LevelFilter, __lookup_logging_process, info};
 
@@ -88,7 +269,7 @@ fn log_server_start<A: ToSocketAddrs>(addr: A) {
             };
             ansi_term::Style::new()
                 .bold()
-                .paint(format!("http://{}:{}", ip_string, addr.port()))
+                .paint(format!("{}://{}:{}", scheme, ip_string, addr.port()))
                 .to_string()
         })
         .collect::<Vec<_>>()
@@ -97,4 +278,17 @@ fn log_server_start<A: ToSocketAddrs>(addr: A) {
 }
 
 #[cfg(not(feature = "logging"))]
-fn log_server_start<A: ToSocketAddrs>(_addr: A) {}
+fn log_server_start<A: ToSocketAddrs>(_addr: A, _scheme: &str) {}
+
+#[cfg(feature = "logging")]
+fn log_routes(routes: &[RouteInfo]) {
+    use lunatic_log::info;
+
+    info!("Routes:");
+    for route in routes {
+        info!("  {route}");
+    }
+}
+
+#[cfg(not(feature = "logging"))]
+fn log_routes(_routes: &[RouteInfo]) {}
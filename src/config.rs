@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Limits and timeouts used by the server when handling connections.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use submillisecond::{router, Application, ServerConfig};
+///
+/// fn index() -> &'static str { "Welcome" }
+///
+/// Application::new(router! {
+///     GET "/" => index
+/// })
+/// .with_config(ServerConfig {
+///     max_body_size: 1024 * 1024,
+///     request_timeout: Duration::from_secs(30),
+///     ..Default::default()
+/// })
+/// .serve("0.0.0.0:3000")
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// The maximum size of a request body in bytes. Requests with larger
+    /// bodies are answered with `413 Payload Too Large`. The default value is
+    /// 10 MiB.
+    pub max_body_size: usize,
+    /// The maximum number of request headers. Requests with more headers are
+    /// answered with `431 Request Header Fields Too Large`. The default value
+    /// is 128.
+    pub max_headers: usize,
+    /// The maximum size of the request line and headers in bytes. The default
+    /// value is 64 KiB.
+    pub max_headers_size: usize,
+    /// The maximum time a handler can spend on a request, before it's
+    /// answered with `408 Request Timeout`. The default value is 5 minutes.
+    pub request_timeout: Duration,
+    /// The maximum time for receiving the request line and headers, starting
+    /// when the first data arrives (or when a new connection is accepted).
+    /// Slower requests are answered with `408 Request Timeout`. Reading the
+    /// request body is limited by this timeout for each read, and by the
+    /// [`request_timeout`](ServerConfig::request_timeout) as a whole. The
+    /// default value is 30 seconds.
+    pub header_read_timeout: Duration,
+    /// The maximum time a kept alive connection stays idle waiting for the next
+    /// request. Idle connections are closed without a response. The default
+    /// value is 60 seconds.
+    pub keep_alive_timeout: Duration,
+    /// The size of the buffer used for reading from the TCP stream. The
+    /// default value is 4 KiB.
+    pub read_buffer_size: usize,
+    /// The maximum time active connections get to finish their current request
+    /// when the server is shut down with a
+    /// [`ShutdownHandle`](crate::ShutdownHandle). The default value is 30
+    /// seconds.
+    pub shutdown_timeout: Duration,
+    /// The maximum number of connections handled at the same time. Additional
+    /// connections are handled according to the
+    /// [`connection_limit_policy`](ServerConfig::connection_limit_policy). The
+    /// default value is `None`, which doesn't limit connections.
+    pub max_connections: Option<usize>,
+    /// The maximum number of connections from the same IP address handled at
+    /// the same time. Additional connections are handled according to the
+    /// [`connection_limit_policy`](ServerConfig::connection_limit_policy). The
+    /// default value is `None`, which doesn't limit connections.
+    pub max_connections_per_ip: Option<usize>,
+    /// What happens to connections exceeding
+    /// [`max_connections`](ServerConfig::max_connections) or
+    /// [`max_connections_per_ip`](ServerConfig::max_connections_per_ip). The
+    /// default value is [`ConnectionLimitPolicy::Hold`].
+    pub connection_limit_policy: ConnectionLimitPolicy,
+    /// The maximum number of connections held by the
+    /// [`Hold`](ConnectionLimitPolicy::Hold) policy while their IP address is
+    /// at its limit. Additional connections are answered with
+    /// `503 Service Unavailable`. The default value is 1024.
+    pub max_held_connections: usize,
+    /// The maximum time a connection is held by the
+    /// [`Hold`](ConnectionLimitPolicy::Hold) policy. Connections held longer
+    /// are answered with `503 Service Unavailable`, asking the client to retry
+    /// after the same duration. The default value is 30 seconds.
+    pub hold_timeout: Duration,
+    /// Whether responses get a `Date` header, unless the handler already set
+    /// one. The default value is `true`.
+    pub date_header: bool,
+    /// The value of the `Server` header added to responses, unless the handler
+    /// already set one. The default value is `None`, which doesn't add the
+    /// header.
+    pub server_header: Option<String>,
+}
+
+/// Handling of connections exceeding the connection limits of
+/// [`ServerConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionLimitPolicy {
+    /// Hold connections until other connections are closed. Once
+    /// [`max_connections`](ServerConfig::max_connections) is reached, no more
+    /// connections are accepted. Connections exceeding
+    /// [`max_connections_per_ip`](ServerConfig::max_connections_per_ip) are
+    /// held within the limits of
+    /// [`max_held_connections`](ServerConfig::max_held_connections) and
+    /// [`hold_timeout`](ServerConfig::hold_timeout).
+    #[default]
+    Hold,
+    /// Answer connections with `503 Service Unavailable`, asking the client to
+    /// retry after the duration with a `Retry-After` header.
+    Reject {
+        /// The duration sent in the `Retry-After` header, rounded up to whole
+        /// seconds.
+        retry_after: Duration,
+    },
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            max_body_size: 10 * 1024 * 1024,
+            max_headers: 128,
+            max_headers_size: 64 * 1024,
+            request_timeout: Duration::from_secs(5 * 60),
+            header_read_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(60),
+            read_buffer_size: 4096,
+            shutdown_timeout: Duration::from_secs(30),
+            max_connections: None,
+            max_connections_per_ip: None,
+            connection_limit_policy: ConnectionLimitPolicy::Hold,
+            max_held_connections: 1024,
+            hold_timeout: Duration::from_secs(30),
+            date_header: true,
+            server_header: None,
+        }
+    }
+}
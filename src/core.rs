@@ -1,25 +1,34 @@
-use std::io::Read;
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::time::Instant;
+use std::{error, fmt};
 
 use httparse::{self, Status, EMPTY_HEADER};
-use lunatic::net::TcpStream;
+use serde::{Deserialize, Serialize};
 
-const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
-const REQUEST_BUFFER_SIZE: usize = 4096;
-const MAX_HEADERS: usize = 128;
+use crate::{ServerConfig, Stream};
+
+const MAX_URI_LENGTH: usize = 8 * 1024;
 
 /// The request body.
-#[derive(Debug, Clone, Copy)]
-pub struct Body<'a>(&'a [u8]);
+#[derive(Debug, Clone, Default)]
+pub struct Body<'a>(Cow<'a, [u8]>);
 
 impl<'a> Body<'a> {
     /// Create a request body from a slice.
     pub fn from_slice(slice: &'a [u8]) -> Self {
-        Self(slice)
+        Self(Cow::Borrowed(slice))
+    }
+
+    /// Create a request body owning its data.
+    pub fn from_vec(vec: Vec<u8>) -> Body<'static> {
+        Body(Cow::Owned(vec))
     }
 
     /// Returns the request body as a slice.
     pub fn as_slice(&self) -> &[u8] {
-        self.0
+        &self.0
     }
 
     /// Returns the body length in bytes.
@@ -43,16 +52,22 @@ type NextRequest = Vec<u8>;
 /// One TCP read can yield multiple pipelined requests. We keep the data of the
 /// next request(s) around (without parsing it) and seed the next handler
 /// process with it.
+///
+/// If the body didn't arrive together with the request head, the request comes
+/// with an empty body and a [`BodyReader`] to read it. In this case the next
+/// request only starts after the body was consumed.
 pub(crate) struct PipelinedRequests<'a> {
     request: RequestResult<'a>,
     next: NextRequest,
+    body_reader: Option<BodyReader>,
 }
 
 impl<'a> PipelinedRequests<'a> {
     /// Returns the result of parsing the first request + data belonging to
-    /// other pipelined requests.
-    pub(crate) fn pipeline(self) -> (RequestResult<'a>, NextRequest) {
-        (self.request, self.next)
+    /// other pipelined requests, or the reader for a body that is still
+    /// incoming.
+    pub(crate) fn pipeline(self) -> (RequestResult<'a>, NextRequest, Option<BodyReader>) {
+        (self.request, self.next, self.body_reader)
     }
 }
 
@@ -63,6 +78,7 @@ impl<'a> PipelinedRequests<'a> {
         PipelinedRequests {
             request: Ok(request),
             next: Vec::new(),
+            body_reader: None,
         }
     }
 
@@ -72,6 +88,28 @@ impl<'a> PipelinedRequests<'a> {
         PipelinedRequests {
             request: Ok(request),
             next,
+            body_reader: None,
+        }
+    }
+
+    /// The request head is complete, but the body needs to be read from the
+    /// TCP stream.
+    fn from_body_reader(request: http::Request<Body<'a>>, body_reader: BodyReader) -> Self {
+        PipelinedRequests {
+            request: Ok(request),
+            next: Vec::new(),
+            body_reader: Some(body_reader),
+        }
+    }
+
+    /// The connection starts with the HTTP/2 connection preface, followed by
+    /// `next`.
+    #[cfg(feature = "http2")]
+    fn from_http2_preface(next: Vec<u8>) -> Self {
+        PipelinedRequests {
+            request: Err(ParseRequestError::Http2Preface),
+            next,
+            body_reader: None,
         }
     }
 
@@ -81,16 +119,33 @@ impl<'a> PipelinedRequests<'a> {
         PipelinedRequests {
             request: Err(err),
             next: Vec::new(),
+            body_reader: None,
         }
     }
 }
 
+/// Parses the next request from `request_buffer`, reading more data from the
+/// `stream` if necessary.
+///
+/// The request head has to arrive within the header read timeout, starting
+/// when the connection is accepted, or with the first data of the request on a
+/// kept alive connection. If no data for the request arrives in time, parsing
+/// fails with [`ParseRequestError::TcpStreamClosedWithoutData`], otherwise with
+/// [`ParseRequestError::ReadTimeout`].
 pub(crate) fn parse_requests<'a>(
     request_buffer: &'a mut Vec<u8>,
-    stream: &mut TcpStream,
+    stream: &mut Stream,
+    config: &ServerConfig,
+    keep_alive: bool,
 ) -> PipelinedRequests<'a> {
-    let mut buffer = [0_u8; REQUEST_BUFFER_SIZE];
-    let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+    let mut buffer = vec![0_u8; config.read_buffer_size];
+    let mut headers = vec![EMPTY_HEADER; config.max_headers];
+    // A kept alive connection can stay idle until the next request starts.
+    let mut deadline = if keep_alive && request_buffer.is_empty() {
+        None
+    } else {
+        Some(Instant::now() + config.header_read_timeout)
+    };
 
     // Loop until at least one complete request is read.
     let (request_raw, offset) = loop {
@@ -105,29 +160,77 @@ pub(crate) fn parse_requests<'a>(
                     break (request_raw, offset);
                 }
                 Status::Partial => {
-                    // Read more data from TCP stream
-                    let n = stream.read(&mut buffer);
+                    // Read more data from TCP stream, for no longer than the time left
+                    // until the deadline.
+                    let timeout = match deadline {
+                        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                        None => config.keep_alive_timeout,
+                    };
+                    let n = if timeout.is_zero() {
+                        Err(io::ErrorKind::TimedOut.into())
+                    } else {
+                        let _ = stream.set_read_timeout(Some(timeout));
+                        stream.read(&mut buffer)
+                    };
                     if n.is_err() || *n.as_ref().unwrap() == 0 {
                         if request_buffer.is_empty() {
                             return PipelinedRequests::from_err(
                                 ParseRequestError::TcpStreamClosedWithoutData,
                             );
+                        } else if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                            return PipelinedRequests::from_err(ParseRequestError::ReadTimeout);
                         } else {
                             return PipelinedRequests::from_err(ParseRequestError::TcpStreamClosed);
                         }
                     }
+                    // The time for receiving the request starts with its first data.
+                    if deadline.is_none() {
+                        deadline = Some(Instant::now() + config.header_read_timeout);
+                    }
                     // Invalidate references in `headers` that could point to the previous
                     // `request_buffer` before extending it.
-                    headers = [EMPTY_HEADER; MAX_HEADERS];
+                    headers = vec![EMPTY_HEADER; config.max_headers];
                     request_buffer.extend(&buffer[..(n.unwrap())]);
-                    // If request passed max size, abort
-                    if request_buffer.len() > MAX_REQUEST_SIZE {
-                        return PipelinedRequests::from_err(ParseRequestError::RequestTooLarge);
+                    // If request head passed max size, abort
+                    if request_buffer.len() > config.max_headers_size {
+                        // If not even the request line is complete, the uri is too long
+                        if request_buffer.contains(&b'\n') {
+                            return PipelinedRequests::from_err(ParseRequestError::HeadersTooLarge);
+                        } else {
+                            return PipelinedRequests::from_err(ParseRequestError::UriTooLong);
+                        }
                     }
                 }
             },
             Err(err) => {
-                return PipelinedRequests::from_err(ParseRequestError::HttpParseError(err));
+                // httparse rejects obsolete line folding as an invalid header name,
+                // report it separately because it's commonly used in request smuggling.
+                if err == httparse::Error::HeaderName && has_obsolete_line_folding(request_buffer) {
+                    return PipelinedRequests::from_err(ParseRequestError::ObsoleteLineFolding);
+                }
+                // The HTTP/2 connection preface looks like a request with an unsupported
+                // version, the rest of it is read to make sure.
+                #[cfg(feature = "http2")]
+                if err == httparse::Error::Version && starts_http2_preface(request_buffer) {
+                    let preface = crate::http2::PREFACE;
+                    let mut rest = vec![0_u8; preface.len().saturating_sub(request_buffer.len())];
+                    let timeout = deadline.map_or(config.header_read_timeout, |deadline| {
+                        deadline.saturating_duration_since(Instant::now())
+                    });
+                    if timeout.is_zero() {
+                        return PipelinedRequests::from_err(ParseRequestError::ReadTimeout);
+                    }
+                    let _ = stream.set_read_timeout(Some(timeout));
+                    if stream.read_exact(&mut rest).is_err() {
+                        return PipelinedRequests::from_err(ParseRequestError::TcpStreamClosed);
+                    }
+                    request_buffer.extend(rest);
+                    if request_buffer.starts_with(preface) {
+                        let next = request_buffer[preface.len()..].to_vec();
+                        return PipelinedRequests::from_http2_preface(next);
+                    }
+                }
+                return PipelinedRequests::from_err(err.into());
             }
         }
     };
@@ -141,69 +244,519 @@ pub(crate) fn parse_requests<'a>(
             return PipelinedRequests::from_err(ParseRequestError::UnknownMethod);
         }
     };
-    let request = http::Request::builder()
+    let path = request_raw.path.unwrap();
+    if path.len() > MAX_URI_LENGTH {
+        return PipelinedRequests::from_err(ParseRequestError::UriTooLong);
+    }
+    let uri = match http::Uri::try_from(path) {
+        Ok(uri) => uri,
+        Err(_) => {
+            return PipelinedRequests::from_err(ParseRequestError::InvalidUri);
+        }
+    };
+    let version = match request_raw.version {
+        Some(0) => http::Version::HTTP_10,
+        _ => http::Version::HTTP_11,
+    };
+    let mut request = http::Request::builder()
         .method(method)
-        .uri(request_raw.path.unwrap());
+        .uri(uri)
+        .version(version);
     let mut content_length = None;
-    let request = request_raw.headers.iter().fold(request, |request, header| {
-        if header.name.to_lowercase() == "content-length" {
-            let value_string = std::str::from_utf8(header.value).unwrap();
-            let length = value_string.parse::<usize>().unwrap();
-            content_length = Some(length);
-        }
-        request.header(header.name, header.value)
-    });
-    // If content-length exists, request has a body
-    if let Some(content_length) = content_length {
-        #[allow(clippy::comparison_chain)]
-        if request_buffer[offset..].len() == content_length {
-            // Complete content is captured from the request w/o trailing pipelined
-            // requests.
-            PipelinedRequests::from_complete(
-                request
-                    .body(Body::from_slice(&request_buffer[offset..]))
-                    .unwrap(),
-            )
-        } else if request_buffer[offset..].len() > content_length {
-            // Complete content is captured from the request with trailing pipelined
-            // requests.
-            PipelinedRequests::from_pipeline(
-                request
-                    .body(Body::from_slice(&request_buffer[offset..]))
-                    .unwrap(),
-                Vec::from(&request_buffer[offset + content_length..]),
+    let mut transfer_codings = Vec::new();
+    let mut expect_continue = false;
+    for header in request_raw.headers.iter() {
+        if header.name.eq_ignore_ascii_case("content-length") {
+            // A second content-length, even with the same value, could be interpreted
+            // differently by a proxy in front of us.
+            if content_length.is_some() {
+                return PipelinedRequests::from_err(ParseRequestError::DuplicateContentLength);
+            }
+            match parse_content_length(header.value) {
+                Some(length) => content_length = Some(length),
+                None => {
+                    return PipelinedRequests::from_err(ParseRequestError::InvalidContentLength);
+                }
+            }
+        }
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            match std::str::from_utf8(header.value) {
+                Ok(value) => transfer_codings.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|coding| !coding.is_empty()),
+                ),
+                Err(_) => {
+                    return PipelinedRequests::from_err(
+                        ParseRequestError::UnsupportedTransferEncoding,
+                    );
+                }
+            }
+        }
+        // HTTP/1.0 clients don't know about expectations, so the header is ignored
+        if header.name.eq_ignore_ascii_case("expect") && version == http::Version::HTTP_11 {
+            if header.value.eq_ignore_ascii_case(b"100-continue") {
+                expect_continue = true;
+            } else {
+                return PipelinedRequests::from_err(ParseRequestError::ExpectationFailed);
+            }
+        }
+        request = request.header(header.name, header.value);
+    }
+    // The body length can only be determined by one of the headers, otherwise
+    // it's ambiguous which one was used by a proxy forwarding the request.
+    if content_length.is_some() && !transfer_codings.is_empty() {
+        return PipelinedRequests::from_err(ParseRequestError::ContentLengthWithTransferEncoding);
+    }
+    // If a transfer-encoding is present, chunked must be applied exactly once and
+    // as the last transfer coding, otherwise the body length can't be determined.
+    let chunked = match transfer_codings.split_last() {
+        Some((last, rest)) => {
+            if !last.eq_ignore_ascii_case("chunked")
+                || rest
+                    .iter()
+                    .any(|coding| coding.eq_ignore_ascii_case("chunked"))
+            {
+                return PipelinedRequests::from_err(ParseRequestError::UnsupportedTransferEncoding);
+            }
+            true
+        }
+        None => false,
+    };
+    if content_length.unwrap_or(0) > config.max_body_size {
+        return PipelinedRequests::from_err(ParseRequestError::RequestTooLarge);
+    }
+    // Find out where the body is located in the `request_buffer` and where the
+    // next request starts. If the body didn't fully arrive yet, it's read from the
+    // TCP stream once the handler needs it.
+    let (body, next, body_state) = if chunked {
+        // If transfer-encoding is chunked, the body is decoded from the chunks.
+        match parse_chunked_body(&request_buffer[offset..], config) {
+            Ok(Status::Complete((chunks, encoded_len))) => {
+                // Move the chunk data together, directly after the request head. The
+                // decoded body is always shorter than the encoded one, so it's safe to
+                // do in place.
+                let mut body_len = 0;
+                for chunk in chunks {
+                    let chunk_len = chunk.len();
+                    request_buffer.copy_within(
+                        (offset + chunk.start)..(offset + chunk.end),
+                        offset + body_len,
+                    );
+                    body_len += chunk_len;
+                }
+                (offset..(offset + body_len), offset + encoded_len, None)
+            }
+            Ok(Status::Partial) => (offset..offset, offset, Some(BodyState::ChunkSize)),
+            Err(err) => return PipelinedRequests::from_err(err),
+        }
+    } else if let Some(content_length) = content_length {
+        // If content-length exists, request has a body
+        if request_buffer[offset..].len() < content_length {
+            (
+                offset..offset,
+                offset,
+                Some(BodyState::Length(content_length)),
             )
         } else {
-            // Read the rest from TCP stream to form a full request
-            let rest = content_length - request_buffer[offset..].len();
-            let mut buffer = vec![0u8; rest];
-            stream.read_exact(&mut buffer).unwrap();
-            request_buffer.extend(&buffer);
-            PipelinedRequests::from_complete(
-                request
-                    .body(Body::from_slice(&request_buffer[offset..]))
-                    .unwrap(),
+            (
+                offset..(offset + content_length),
+                offset + content_length,
+                None,
             )
         }
     } else {
-        // If the offset points to the end of `requests_buffer` we have a full request,
-        // w/o a trailing pipelined request.
-        if request_buffer[offset..].is_empty() {
-            PipelinedRequests::from_complete(request.body(Body::from_slice(&[])).unwrap())
-        } else {
-            PipelinedRequests::from_pipeline(
-                request.body(Body::from_slice(&[])).unwrap(),
-                Vec::from(&request_buffer[offset..]),
-            )
+        (offset..offset, offset, None)
+    };
+
+    let request = match request.body(Body::from_slice(&request_buffer[body])) {
+        Ok(request) => request,
+        Err(_) => {
+            return PipelinedRequests::from_err(ParseRequestError::InvalidHeader);
+        }
+    };
+    if let Some(state) = body_state {
+        let body_reader = BodyReader::new(
+            stream.clone(),
+            Vec::from(&request_buffer[offset..]),
+            state,
+            config,
+            expect_continue,
+        );
+        return PipelinedRequests::from_body_reader(request, body_reader);
+    }
+    // If the body ends at the end of `requests_buffer` we have a full request,
+    // w/o a trailing pipelined request.
+    if request_buffer[next..].is_empty() {
+        PipelinedRequests::from_complete(request)
+    } else {
+        PipelinedRequests::from_pipeline(request, Vec::from(&request_buffer[next..]))
+    }
+}
+
+/// Parses a content-length header value.
+///
+/// Only digits are allowed, so values such as `+5` which would be accepted by
+/// [`str::parse`] are rejected.
+fn parse_content_length(value: &[u8]) -> Option<usize> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Checks if the request head in `buffer` contains a header line continued with
+/// obsolete line folding (a line starting with a space or tab).
+fn has_obsolete_line_folding(buffer: &[u8]) -> bool {
+    let head_end = buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap_or(buffer.len());
+    buffer[..head_end]
+        .windows(3)
+        .any(|window| window == b"\r\n " || window == b"\r\n\t")
+}
+
+/// Checks if the bytes received so far in `buffer` match the HTTP/2
+/// connection preface, which the first read can end anywhere in.
+#[cfg(feature = "http2")]
+fn starts_http2_preface(buffer: &[u8]) -> bool {
+    let received = buffer.len().min(crate::http2::PREFACE.len());
+    buffer[..received] == crate::http2::PREFACE[..received]
+}
+
+/// Parses a body with chunked transfer encoding from the start of `buffer`.
+///
+/// If the body is complete, the ranges of chunk data inside the `buffer` are
+/// returned together with the length of the whole encoded body, including the
+/// last chunk and trailers. Chunk extensions and trailers are ignored.
+fn parse_chunked_body(
+    buffer: &[u8],
+    config: &ServerConfig,
+) -> Result<Status<(Vec<Range<usize>>, usize)>, ParseRequestError> {
+    let mut chunks = Vec::new();
+    let mut body_len = 0;
+    let mut pos = 0;
+    loop {
+        let (size_len, size) = match httparse::parse_chunk_size(&buffer[pos..]) {
+            Ok(Status::Complete(chunk_size)) => chunk_size,
+            Ok(Status::Partial) => return Ok(Status::Partial),
+            Err(_) => return Err(ParseRequestError::InvalidChunkedBody),
+        };
+        pos += size_len;
+        if size == 0 {
+            break;
+        }
+        if size > (config.max_body_size - body_len) as u64 {
+            return Err(ParseRequestError::RequestTooLarge);
+        }
+        body_len += size as usize;
+        let end = pos + size as usize;
+        // Chunk data is followed by a CRLF
+        if buffer.len() < end + 2 {
+            return Ok(Status::Partial);
+        }
+        if &buffer[end..(end + 2)] != b"\r\n" {
+            return Err(ParseRequestError::InvalidChunkedBody);
+        }
+        chunks.push(pos..end);
+        pos = end + 2;
+    }
+    // The last chunk is followed by optional trailers and an empty line
+    let mut trailers = vec![EMPTY_HEADER; config.max_headers];
+    match httparse::parse_headers(&buffer[pos..], &mut trailers) {
+        Ok(Status::Complete((trailers_len, _))) => {
+            Ok(Status::Complete((chunks, pos + trailers_len)))
+        }
+        Ok(Status::Partial) => Ok(Status::Partial),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The part of the body a [`BodyReader`] is going to read next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BodyState {
+    /// The remaining length of a body with content-length.
+    Length(usize),
+    /// The size line of the next chunk.
+    ChunkSize,
+    /// The remaining length of the current chunk.
+    ChunkData(usize),
+    /// The CRLF following chunk data.
+    ChunkEnd,
+    /// The trailers after the last chunk.
+    Trailers,
+    /// The whole body was read.
+    Done,
+}
+
+/// Reads a request body from the TCP stream as it's consumed.
+///
+/// Data that arrived together with the request head is read first. Data read
+/// from the TCP stream past the end of the body stays in the buffer, so once
+/// the body is consumed, the rest of the buffer belongs to the next pipelined
+/// request.
+pub(crate) struct BodyReader {
+    stream: Stream,
+    buffer: Vec<u8>,
+    pos: usize,
+    state: BodyState,
+    /// Length of the decoded body so far.
+    body_len: usize,
+    max_body_size: usize,
+    max_headers_size: usize,
+    read_buffer_size: usize,
+    /// The client is waiting for `100 Continue` before sending the body.
+    expect_continue: bool,
+}
+
+impl BodyReader {
+    fn new(
+        mut stream: Stream,
+        buffer: Vec<u8>,
+        state: BodyState,
+        config: &ServerConfig,
+        expect_continue: bool,
+    ) -> Self {
+        // Each read of the body is limited by the header read timeout, while the
+        // whole body is limited by the request timeout of the handler reading it.
+        let _ = stream.set_read_timeout(Some(config.header_read_timeout));
+        BodyReader {
+            stream,
+            // If some of the body arrived already, the client didn't wait and the
+            // interim response is not needed anymore.
+            expect_continue: expect_continue && buffer.is_empty(),
+            buffer,
+            pos: 0,
+            state,
+            body_len: 0,
+            max_body_size: config.max_body_size,
+            max_headers_size: config.max_headers_size,
+            read_buffer_size: config.read_buffer_size,
+        }
+    }
+
+    /// Reads the rest of the body and returns the data belonging to the next
+    /// pipelined request.
+    ///
+    /// Returns `None` if the connection can't be reused, because the body is
+    /// invalid or the client never got to send it.
+    pub(crate) fn finish(&mut self) -> Option<Vec<u8>> {
+        // Without the interim response, the client decides whether it sends the
+        // body or not, so it's unknown where the next request starts.
+        if self.expect_continue && self.state != BodyState::Done {
+            return None;
+        }
+        io::copy(self, &mut io::sink()).ok()?;
+        Some(self.buffer.split_off(self.pos))
+    }
+
+    /// Reads raw body data, from the buffer first and then from the TCP stream.
+    fn read_raw(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buffer.len() {
+            let n = (&self.buffer[self.pos..]).read(buf)?;
+            self.pos += n;
+            return Ok(n);
+        }
+        self.send_continue()?;
+        match self.stream.read(buf)? {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            n => Ok(n),
+        }
+    }
+
+    /// Reads one line of chunk framing, including the line ending.
+    ///
+    /// Data read from the TCP stream after the line stays in the buffer, to be
+    /// read next.
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            let unread = &self.buffer[self.pos..];
+            if let Some(end) = unread.iter().position(|byte| *byte == b'\n') {
+                let line = unread[..=end].to_vec();
+                self.pos += end + 1;
+                return Ok(line);
+            }
+            if unread.len() >= self.max_headers_size {
+                return Err(body_error(ParseRequestError::InvalidChunkedBody));
+            }
+            self.fill_buffer()?;
+        }
+    }
+
+    /// Reads more data from the TCP stream into the buffer, dropping the data
+    /// that was already read from it.
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        self.send_continue()?;
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        let len = self.buffer.len();
+        self.buffer.resize(len + self.read_buffer_size, 0);
+        let result = self.stream.read(&mut self.buffer[len..]);
+        self.buffer.truncate(len + *result.as_ref().unwrap_or(&0));
+        match result? {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends the `100 Continue` interim response if the client waits for it.
+    fn send_continue(&mut self) -> io::Result<()> {
+        if self.expect_continue {
+            self.stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            self.expect_continue = false;
+        }
+        Ok(())
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.state {
+                BodyState::Length(0) | BodyState::Done => {
+                    self.state = BodyState::Done;
+                    return Ok(0);
+                }
+                BodyState::Length(remaining) => {
+                    let len = buf.len().min(remaining);
+                    let n = self.read_raw(&mut buf[..len])?;
+                    self.state = BodyState::Length(remaining - n);
+                    return Ok(n);
+                }
+                BodyState::ChunkSize => {
+                    let line = self.read_line()?;
+                    let size = match httparse::parse_chunk_size(&line) {
+                        Ok(Status::Complete((_, size))) => size,
+                        _ => return Err(body_error(ParseRequestError::InvalidChunkedBody)),
+                    };
+                    if size == 0 {
+                        self.state = BodyState::Trailers;
+                    } else if size > (self.max_body_size - self.body_len) as u64 {
+                        return Err(body_error(ParseRequestError::RequestTooLarge));
+                    } else {
+                        self.body_len += size as usize;
+                        self.state = BodyState::ChunkData(size as usize);
+                    }
+                }
+                BodyState::ChunkData(remaining) => {
+                    let len = buf.len().min(remaining);
+                    let n = self.read_raw(&mut buf[..len])?;
+                    self.state = if n == remaining {
+                        BodyState::ChunkEnd
+                    } else {
+                        BodyState::ChunkData(remaining - n)
+                    };
+                    return Ok(n);
+                }
+                BodyState::ChunkEnd => {
+                    if self.read_line()? != b"\r\n" {
+                        return Err(body_error(ParseRequestError::InvalidChunkedBody));
+                    }
+                    self.state = BodyState::ChunkSize;
+                }
+                BodyState::Trailers => {
+                    // Trailers are ignored, but limited by the same size as headers
+                    let mut trailers_len = 0;
+                    loop {
+                        let line = self.read_line()?;
+                        trailers_len += line.len();
+                        if trailers_len > self.max_headers_size {
+                            return Err(body_error(ParseRequestError::HeadersTooLarge));
+                        }
+                        if line == b"\r\n" {
+                            break;
+                        }
+                    }
+                    self.state = BodyState::Done;
+                }
+            }
         }
     }
 }
 
-#[derive(Debug)]
+/// Wraps an error of an invalid body, to be returned from [`BodyReader`].
+fn body_error(err: ParseRequestError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Checks if reading a body failed because it was larger than allowed.
+pub(crate) fn is_body_too_large(err: &io::Error) -> bool {
+    matches!(
+        err.get_ref()
+            .and_then(|err| err.downcast_ref::<ParseRequestError>()),
+        Some(ParseRequestError::RequestTooLarge)
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub(crate) enum ParseRequestError {
     TcpStreamClosed,
     TcpStreamClosedWithoutData,
-    HttpParseError(httparse::Error),
+    ReadTimeout,
+    HttpParseError(String),
+    TooManyHeaders,
+    HeadersTooLarge,
+    UriTooLong,
+    InvalidUri,
+    InvalidHeader,
+    InvalidContentLength,
+    DuplicateContentLength,
+    ContentLengthWithTransferEncoding,
+    UnsupportedTransferEncoding,
+    ObsoleteLineFolding,
+    ExpectationFailed,
+    InvalidChunkedBody,
     RequestTooLarge,
     UnknownMethod,
+    #[cfg(feature = "http2")]
+    Http2Preface,
+}
+
+impl From<httparse::Error> for ParseRequestError {
+    fn from(err: httparse::Error) -> Self {
+        match err {
+            httparse::Error::TooManyHeaders => ParseRequestError::TooManyHeaders,
+            err => ParseRequestError::HttpParseError(err.to_string()),
+        }
+    }
 }
+
+impl fmt::Display for ParseRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRequestError::TcpStreamClosed => write!(f, "tcp stream closed"),
+            ParseRequestError::TcpStreamClosedWithoutData => {
+                write!(f, "tcp stream closed without data")
+            }
+            ParseRequestError::ReadTimeout => write!(f, "timed out reading request"),
+            ParseRequestError::HttpParseError(err) => write!(f, "{err}"),
+            ParseRequestError::TooManyHeaders => write!(f, "too many headers"),
+            ParseRequestError::HeadersTooLarge => write!(f, "headers too large"),
+            ParseRequestError::UriTooLong => write!(f, "uri too long"),
+            ParseRequestError::InvalidUri => write!(f, "invalid uri"),
+            ParseRequestError::InvalidHeader => write!(f, "invalid header"),
+            ParseRequestError::InvalidContentLength => write!(f, "invalid content-length"),
+            ParseRequestError::DuplicateContentLength => write!(f, "duplicate content-length"),
+            ParseRequestError::ContentLengthWithTransferEncoding => {
+                write!(f, "both content-length and transfer-encoding present")
+            }
+            ParseRequestError::UnsupportedTransferEncoding => {
+                write!(f, "unsupported transfer-encoding")
+            }
+            ParseRequestError::ObsoleteLineFolding => write!(f, "obsolete line folding"),
+            ParseRequestError::ExpectationFailed => write!(f, "unsupported expectation"),
+            ParseRequestError::InvalidChunkedBody => write!(f, "invalid chunked body"),
+            ParseRequestError::RequestTooLarge => write!(f, "request too large"),
+            ParseRequestError::UnknownMethod => write!(f, "unknown method"),
+            #[cfg(feature = "http2")]
+            ParseRequestError::Http2Preface => write!(f, "http/2 connection preface"),
+        }
+    }
+}
+
+impl error::Error for ParseRequestError {}
@@ -10,3 +10,24 @@ pub fn err_404() -> Response {
         .body(b"<h1>404: Not found</h1>".to_vec())
         .unwrap()
 }
+
+/// Return an error 405 method not allowed response, with the allowed methods
+/// in the `Allow` header.
+pub fn err_405(allow: &str) -> Response {
+    Response::builder()
+        .status(405)
+        .header("Allow", allow)
+        .header("Content-Type", "text/html; charset=UTF-8")
+        .body(b"<h1>405: Method not allowed</h1>".to_vec())
+        .unwrap()
+}
+
+/// Return a response to an `OPTIONS` request, with the allowed methods in the
+/// `Allow` header.
+pub fn options(allow: &str) -> Response {
+    Response::builder()
+        .status(204)
+        .header("Allow", allow)
+        .body(Vec::new())
+        .unwrap()
+}
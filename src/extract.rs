@@ -2,6 +2,7 @@
 //!
 //! Many of the types and implementations were taken from [Axum](https://crates.io/crates/axum).
 
+pub use body_stream::BodyStream;
 pub use host::Host;
 pub use path::Path;
 #[cfg(feature = "query")]
@@ -12,6 +13,7 @@ pub mod path;
 pub mod rejection;
 
 mod body;
+mod body_stream;
 mod header_map;
 mod host;
 #[cfg(feature = "json")]
@@ -1,12 +1,12 @@
-use std::convert::Infallible;
-
+use super::rejection::FailedToBufferBody;
 use super::FromOwnedRequest;
 use crate::{Body, RequestContext};
 
 impl FromOwnedRequest for Body<'static> {
-    type Rejection = Infallible;
+    type Rejection = FailedToBufferBody;
 
-    fn from_owned_request(req: RequestContext) -> Result<Self, Self::Rejection> {
-        Ok(*req.body())
+    fn from_owned_request(mut req: RequestContext) -> Result<Self, Self::Rejection> {
+        req.buffer_body()?;
+        Ok(req.request.into_body())
     }
 }
@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use super::FromRequest;
+use crate::core::{Body, BodyReader};
+
+/// Extractor that reads the request body incrementally.
+///
+/// Unlike [`Body`], the body is not buffered in memory before the handler is
+/// called. It's read from the TCP stream as the handler consumes it through
+/// the [`Read`] implementation, so the client can't send data faster than the
+/// handler processes it.
+///
+/// The body can only be read once. Extractors that buffer the body, like
+/// [`String`] or [`Vec<u8>`], will see an empty body once a `BodyStream` was
+/// extracted.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Read;
+///
+/// use submillisecond::extract::BodyStream;
+///
+/// fn upload(mut body: BodyStream) -> String {
+///     let mut buffer = [0; 4096];
+///     let mut total = 0;
+///     loop {
+///         match body.read(&mut buffer) {
+///             Ok(0) => break,
+///             Ok(n) => total += n,
+///             Err(_) => return "Upload failed".to_string(),
+///         }
+///     }
+///     format!("Received {total} bytes")
+/// }
+/// ```
+pub struct BodyStream(BodyStreamInner);
+
+enum BodyStreamInner {
+    /// The body arrived together with the request head.
+    Buffered(Body<'static>, usize),
+    /// The body is read from the TCP stream.
+    Stream(Rc<RefCell<BodyReader>>),
+}
+
+impl FromRequest for BodyStream {
+    type Rejection = Infallible;
+
+    fn from_request(req: &mut crate::RequestContext) -> Result<Self, Self::Rejection> {
+        match req.take_body_reader() {
+            Some(body_reader) => Ok(BodyStream(BodyStreamInner::Stream(body_reader))),
+            None => {
+                let body = std::mem::take(req.body_mut());
+                Ok(BodyStream(BodyStreamInner::Buffered(body, 0)))
+            }
+        }
+    }
+}
+
+impl Read for BodyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            BodyStreamInner::Buffered(body, pos) => {
+                let n = (&body.as_slice()[*pos..]).read(buf)?;
+                *pos += n;
+                Ok(n)
+            }
+            BodyStreamInner::Stream(body_reader) => body_reader.borrow_mut().read(buf),
+        }
+    }
+}
@@ -15,6 +15,7 @@ where
         if !json_content_type(req) {
             return Err(MissingJsonContentType.into());
         }
+        req.buffer_body()?;
 
         let value = match serde_json::from_slice(req.body().as_slice()) {
             Ok(value) => value,
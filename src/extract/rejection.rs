@@ -81,6 +81,33 @@ composite_rejection! {
     }
 }
 
+define_rejection! {
+    #[status = PAYLOAD_TOO_LARGE]
+    #[body = "Failed to buffer the request body"]
+    /// Rejection type used if the request body is larger than the configured
+    /// [`max_body_size`](crate::ServerConfig::max_body_size).
+    pub struct LengthLimitError(Error);
+}
+
+define_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Failed to buffer the request body"]
+    /// Rejection type used if the request body couldn't be read from the
+    /// connection, for example if it was closed or the chunked encoding was
+    /// invalid.
+    pub struct UnknownBodyError(Error);
+}
+
+composite_rejection! {
+    /// Rejection used for extractors that buffer the request body.
+    ///
+    /// Contains one variant for each way buffering the body can fail.
+    pub enum FailedToBufferBody {
+        LengthLimitError,
+        UnknownBodyError,
+    }
+}
+
 define_rejection! {
     #[status = BAD_REQUEST]
     #[body = "Request body didn't contain valid UTF-8"]
@@ -94,6 +121,7 @@ composite_rejection! {
     ///
     /// Contains one variant for each way the [`String`] extractor can fail.
     pub enum StringRejection {
+        FailedToBufferBody,
         InvalidUtf8,
     }
 }
@@ -157,6 +185,7 @@ composite_rejection! {
     /// can fail.
     #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     pub enum JsonRejection {
+        FailedToBufferBody,
         JsonDataError,
         JsonSyntaxError,
         MissingJsonContentType,
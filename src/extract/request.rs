@@ -1,5 +1,6 @@
 use std::convert::Infallible;
 
+use super::rejection::FailedToBufferBody;
 use super::FromOwnedRequest;
 use crate::core::Body;
 use crate::RequestContext;
@@ -13,9 +14,10 @@ impl FromOwnedRequest for RequestContext {
 }
 
 impl FromOwnedRequest for http::Request<Body<'static>> {
-    type Rejection = Infallible;
+    type Rejection = FailedToBufferBody;
 
-    fn from_owned_request(req: RequestContext) -> Result<Self, Self::Rejection> {
+    fn from_owned_request(mut req: RequestContext) -> Result<Self, Self::Rejection> {
+        req.buffer_body()?;
         Ok(req.request)
     }
 }
@@ -1,12 +1,12 @@
-use std::convert::Infallible;
-
+use super::rejection::FailedToBufferBody;
 use super::FromOwnedRequest;
 use crate::RequestContext;
 
 impl FromOwnedRequest for Vec<u8> {
-    type Rejection = Infallible;
+    type Rejection = FailedToBufferBody;
 
-    fn from_owned_request(req: RequestContext) -> Result<Self, Self::Rejection> {
+    fn from_owned_request(mut req: RequestContext) -> Result<Self, Self::Rejection> {
+        req.buffer_body()?;
         Ok(Vec::from(req.request.into_body().as_slice()))
     }
 }
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use http::StatusCode;
+use lunatic::function::FuncRef;
+use serde::{Deserialize, Serialize};
+
+use crate::response::{IntoResponse, Response};
+
+/// Function rendering the responses to failures, see
+/// [`Application::with_error_handler`](crate::Application::with_error_handler).
+pub(crate) type ErrorHandler = FuncRef<fn(Failure) -> Response>;
+
+/// The kind of a [`Failure`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// The request could not be parsed.
+    InvalidRequest,
+    /// The request was not received in time.
+    ReadTimeout,
+    /// The handler didn't finish in time.
+    RequestTimeout,
+    /// The handler panicked.
+    Panic,
+    /// The worker process handling the request failed without panicking while
+    /// running with resource limits, most likely by exceeding them.
+    ResourceLimit,
+    /// The connection exceeded the connection limits.
+    ConnectionLimit,
+    /// The request could not be processed.
+    Internal,
+}
+
+/// A failure that happened outside of a handler, answered by the server.
+///
+/// By default the server responds with a plain text response, this can be
+/// customized with
+/// [`Application::with_error_handler`](crate::Application::with_error_handler).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Failure {
+    kind: FailureKind,
+    status: u16,
+    method: Option<String>,
+    uri: Option<String>,
+    message: Option<String>,
+}
+
+impl Failure {
+    pub(crate) fn new(kind: FailureKind, status: StatusCode) -> Self {
+        Failure {
+            kind,
+            status: status.as_u16(),
+            method: None,
+            uri: None,
+            message: None,
+        }
+    }
+
+    /// Adds the method and uri of the failed request.
+    pub(crate) fn with_request(mut self, request: Option<&RequestInfo>) -> Self {
+        if let Some(request) = request {
+            self.method = Some(request.method.clone());
+            self.uri = Some(request.uri.clone());
+        }
+        self
+    }
+
+    /// Adds a message describing the failure.
+    pub(crate) fn with_message(mut self, message: impl fmt::Display) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    /// The kind of failure.
+    pub fn kind(&self) -> FailureKind {
+        self.kind
+    }
+
+    /// The status code the server responds with by default.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// The method of the request, if it was parsed.
+    pub fn method(&self) -> Option<&str> {
+        self.method.as_deref()
+    }
+
+    /// The uri of the request, if it was parsed.
+    pub fn uri(&self) -> Option<&str> {
+        self.uri.as_deref()
+    }
+
+    /// The path of the request, if it was parsed.
+    pub fn path(&self) -> Option<&str> {
+        self.uri
+            .as_deref()
+            .map(|uri| uri.split(['?', '#']).next().unwrap_or_default())
+    }
+
+    /// A message describing the failure, such as the reason a request could
+    /// not be parsed.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// The default plain text response.
+    pub(crate) fn default_response(&self) -> Response {
+        let status = self.status();
+        let body = match self.kind {
+            FailureKind::ReadTimeout | FailureKind::RequestTimeout => "Request timed out",
+            _ => status.canonical_reason().unwrap_or_default(),
+        };
+        (status, body).into_response()
+    }
+}
+
+/// The method and uri of a request, sent by the worker once the request is
+/// parsed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct RequestInfo {
+    pub(crate) method: String,
+    pub(crate) uri: String,
+}
+
+impl RequestInfo {
+    pub(crate) fn new<B>(request: &http::Request<B>) -> Self {
+        RequestInfo {
+            method: request.method().to_string(),
+            uri: request.uri().to_string(),
+        }
+    }
+}
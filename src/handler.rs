@@ -1,10 +1,11 @@
 use lunatic::function::reference::Fn as FnPtr;
 use lunatic::function::FuncRef;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::extract::{FromOwnedRequest, FromRequest};
 use crate::response::IntoResponse;
+use crate::routes::RouteInfo;
 use crate::{RequestContext, Response};
 
 /// Implemented for process-safe [`Handlers`](Handler).
@@ -24,7 +25,8 @@ use crate::{RequestContext, Response};
 /// time and are shared between all processes, so they can be easily used as
 /// handlers. In fact, the [`router!`](crate::router) macro will in the end just
 /// generate a function that will be used as a handler and invoke other handlers
-/// depending on the request values.
+/// depending on the request values, wrapped in a [`RouterHandler`] with the
+/// table of its routes.
 ///
 /// ### Serializable and clonable objects
 ///
@@ -38,6 +40,11 @@ pub trait ProcessSafeHandler<Kind, Arg, Ret> {
 
     /// Turn type into a safe handler.
     fn safe_handler(self) -> Self::SafeHandler;
+
+    /// Returns the routes of the handler, see [`Handler::routes`].
+    fn routes(&self) -> &'static [RouteInfo] {
+        &[]
+    }
 }
 
 /// Marker type for functions that satisfy [`ProcessSafeHandler`].
@@ -66,6 +73,10 @@ where
     fn safe_handler(self) -> Self::SafeHandler {
         self
     }
+
+    fn routes(&self) -> &'static [RouteInfo] {
+        <T as Handler<Arg, Ret>>::routes(self)
+    }
 }
 
 impl<T, Arg, Ret> Handler<Arg, Ret> for FuncRef<T>
@@ -123,6 +134,45 @@ pub trait Handler<Arg = (), Ret = ()> {
 
     /// Initializes handler, useful for spawning processes on startup.
     fn init(&self) {}
+
+    /// Returns the routes of the handler, if it's a router generated by the
+    /// [`router!`](crate::router) macro.
+    fn routes(&self) -> &'static [RouteInfo] {
+        &[]
+    }
+}
+
+/// The handler of a router generated by the [`router!`](crate::router) macro,
+/// with the table of its routes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RouterHandler {
+    handler: FuncRef<fn(RequestContext) -> Response>,
+    // the routes are only read by the application, and not sent to workers
+    #[serde(skip)]
+    routes: &'static [RouteInfo],
+}
+
+impl RouterHandler {
+    /// Creates the handler of a router.
+    ///
+    /// The [`router!`](crate::router) macro uses this internally.
+    #[doc(hidden)]
+    pub fn new(handler: fn(RequestContext) -> Response, routes: &'static [RouteInfo]) -> Self {
+        RouterHandler {
+            handler: FuncRef::new(handler),
+            routes,
+        }
+    }
+}
+
+impl Handler for RouterHandler {
+    fn handle(&self, req: RequestContext) -> Response {
+        (self.handler.get())(req)
+    }
+
+    fn routes(&self) -> &'static [RouteInfo] {
+        self.routes
+    }
 }
 
 impl<F, R> Handler<(), R> for F
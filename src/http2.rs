@@ -0,0 +1,1401 @@
+//! HTTP/2 over cleartext TCP (h2c).
+//!
+//! Connections either start with the HTTP/2 connection preface (prior
+//! knowledge), or are upgraded from HTTP/1.1 with `Upgrade: h2c`. Frames are
+//! read by a separate process and handled by the connection process, which
+//! dispatches each stream to a worker process running the handler.
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64ct::{Base64UrlUnpadded, Encoding};
+use headers::HeaderValue;
+use http::{header, HeaderMap, Request, StatusCode, Version};
+use lunatic::{Mailbox, MailboxError, MessageSignal, Process, ProcessConfig, Tag};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::core::Body;
+use crate::failure::{ErrorHandler, Failure, FailureKind, RequestInfo};
+use crate::response::{Response, StreamBody};
+use crate::supervisor::{
+    insert_server_headers, log_error, log_request, set_panic_hook, worker_failure,
+    SupervisorHandle, WorkerPanic, WorkerResponse,
+};
+use crate::{Handler, RequestContext, ServerConfig, Stream};
+
+/// The connection preface sent by clients.
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const SWITCHING_PROTOCOLS: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: h2c\r\n\r\n";
+
+const FRAME_HEADER_LEN: usize = 9;
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+const MAX_CONCURRENT_STREAMS: usize = 100;
+
+// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// Frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+// Settings
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+// Error codes
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const INTERNAL_ERROR: u32 = 0x2;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const STREAM_CLOSED: u32 = 0x5;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+/// The start of an HTTP/2 connection, detected while parsing an HTTP/1.1
+/// request.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Http2Start {
+    /// The request upgraded with `Upgrade: h2c`, which becomes stream 1.
+    upgrade: Option<Upgrade>,
+    /// Data following the preface or the upgraded request.
+    #[serde(with = "serde_bytes")]
+    next: Vec<u8>,
+}
+
+impl Http2Start {
+    /// The connection started with the connection preface.
+    pub(crate) fn from_preface(next: Vec<u8>) -> Self {
+        Http2Start {
+            upgrade: None,
+            next,
+        }
+    }
+
+    /// The connection is upgraded by `request`, with the decoded
+    /// `HTTP2-Settings` header.
+    pub(crate) fn from_upgrade(
+        request: &Request<Body>,
+        body: Vec<u8>,
+        settings: Vec<u8>,
+        next: Vec<u8>,
+    ) -> Self {
+        let headers = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| !is_connection_specific(name.as_str()))
+            .filter(|(name, _)| name.as_str() != "http2-settings")
+            .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+            .collect();
+        let head = RequestHead {
+            method: request.method().to_string(),
+            uri: request.uri().to_string(),
+            headers,
+            body,
+        };
+        Http2Start {
+            upgrade: Some(Upgrade { head, settings }),
+            next,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Upgrade {
+    head: RequestHead,
+    #[serde(with = "serde_bytes")]
+    settings: Vec<u8>,
+}
+
+/// Checks if the request asks to upgrade the connection to h2c, returning the
+/// decoded `HTTP2-Settings` header.
+pub(crate) fn h2c_upgrade_settings(request: &Request<Body>) -> Option<Vec<u8>> {
+    let headers = request.headers();
+    if request.version() != Version::HTTP_11
+        || !has_token(headers, header::UPGRADE, "h2c")
+        || !has_token(headers, header::CONNECTION, "upgrade")
+        || !has_token(headers, header::CONNECTION, "http2-settings")
+    {
+        return None;
+    }
+    let mut settings = headers.get_all("http2-settings").iter();
+    let value = settings.next()?;
+    if settings.next().is_some() {
+        return None;
+    }
+    Base64UrlUnpadded::decode_vec(value.to_str().ok()?.trim_end_matches('=')).ok()
+}
+
+/// Checks if any header with `name` contains the comma separated `token`.
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// Headers that only apply to HTTP/1.1 connections and are not allowed in
+/// HTTP/2.
+fn is_connection_specific(name: &str) -> bool {
+    matches!(
+        name,
+        "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding" | "upgrade"
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) enum ConnectionMessage {
+    /// A frame was read from the TCP stream.
+    Frame(Frame),
+    /// Reading from the TCP stream failed, with the error code to send to the
+    /// client if it's still connected.
+    ReadFailed(Option<u32>),
+    /// A worker finished handling a stream, or started streaming the response
+    /// body.
+    Response(StreamResponse),
+    /// A chunk of a streaming response body.
+    Data(StreamData),
+    /// A worker failed to handle a stream, and the failure is answered by the
+    /// connection.
+    Failure(u32, Failure),
+    /// A worker panicked, sent by its panic hook.
+    Panicked(u32, WorkerPanic),
+    /// The server is shutting down, no new streams are accepted.
+    Shutdown,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    #[serde(with = "serde_bytes")]
+    payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RequestHead {
+    method: String,
+    uri: String,
+    headers: Vec<(String, Vec<u8>)>,
+    #[serde(with = "serde_bytes")]
+    body: Vec<u8>,
+}
+
+impl RequestHead {
+    /// Builds a request from the decoded header block.
+    ///
+    /// Fails with the status code of the response if the headers are invalid.
+    fn from_fields(
+        fields: Vec<(Vec<u8>, Vec<u8>)>,
+        config: &ServerConfig,
+    ) -> Result<Self, StatusCode> {
+        // The size of a header list is calculated as defined by HTTP/2
+        let size: usize = fields
+            .iter()
+            .map(|(name, value)| name.len() + value.len() + 32)
+            .sum();
+        if fields.len() > config.max_headers || size > config.max_headers_size {
+            return Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+        let mut method = None;
+        let mut path = None;
+        let mut authority = None;
+        let mut headers = Vec::with_capacity(fields.len());
+        for (name, value) in fields {
+            match name.as_slice() {
+                b":method" => method = Some(value),
+                b":path" => path = Some(value),
+                b":authority" => authority = Some(value),
+                b":scheme" => {}
+                name if name.starts_with(b":") => return Err(StatusCode::BAD_REQUEST),
+                _ => {
+                    let name = String::from_utf8(name).map_err(|_| StatusCode::BAD_REQUEST)?;
+                    headers.push((name, value));
+                }
+            }
+        }
+        let method = method.and_then(|method| String::from_utf8(method).ok());
+        let uri = path.and_then(|path| String::from_utf8(path).ok());
+        let (method, uri) = method.zip(uri).ok_or(StatusCode::BAD_REQUEST)?;
+        // Handlers expect the host header, which is replaced by `:authority`
+        if let Some(authority) = authority {
+            if !headers.iter().any(|(name, _)| name == "host") {
+                headers.insert(0, ("host".to_string(), authority));
+            }
+        }
+        Ok(RequestHead {
+            method,
+            uri,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    fn info(&self) -> RequestInfo {
+        RequestInfo {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+        }
+    }
+
+    fn into_request(self) -> http::Result<Request<Body<'static>>> {
+        let mut request = Request::builder()
+            .method(self.method.as_str())
+            .uri(self.uri)
+            .version(Version::HTTP_2);
+        for (name, value) in self.headers {
+            request = request.header(name, value);
+        }
+        request.body(Body::from_vec(self.body))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StreamResponse {
+    stream_id: u32,
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+    #[serde(with = "serde_bytes")]
+    body: Vec<u8>,
+    /// The body is sent by the worker in chunks.
+    streaming: bool,
+}
+
+impl StreamResponse {
+    /// Converts a response with a complete body, setting its length.
+    fn from_response(
+        stream_id: u32,
+        mut response: Response,
+        config: &ServerConfig,
+        is_head: bool,
+    ) -> Self {
+        // Responses to HEAD requests keep the length set by the handler, if they
+        // don't have a body.
+        let content_length = response.body().len();
+        if content_length > 0
+            || !is_head
+            || !response.headers().contains_key(header::CONTENT_LENGTH)
+        {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+        }
+        insert_server_headers(response.headers_mut(), config);
+        if is_head {
+            response.body_mut().clear();
+        }
+        StreamResponse {
+            stream_id,
+            status: response.status().as_u16(),
+            headers: header_fields(response.headers()),
+            body: response.into_body(),
+            streaming: false,
+        }
+    }
+
+    /// Creates the default plain text response to a failure.
+    fn from_failure(stream_id: u32, failure: &Failure, config: &ServerConfig) -> Self {
+        let is_head = failure.method() == Some("HEAD");
+        StreamResponse::from_response(stream_id, failure.default_response(), config, is_head)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StreamData {
+    stream_id: u32,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    /// The chunk is the last one.
+    end_stream: bool,
+}
+
+/// Sends the chunks of a streaming body to the connection process, waiting
+/// for each chunk to be sent before producing the next one.
+struct DataWriter {
+    connection: Process<ConnectionMessage>,
+    stream_id: u32,
+    mailbox: Mailbox<()>,
+}
+
+impl Write for DataWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.connection.send(ConnectionMessage::Data(StreamData {
+            stream_id: self.stream_id,
+            data: buf.to_vec(),
+            end_stream: false,
+        }));
+        self.mailbox.receive();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
+struct StreamRequest<T> {
+    connection: Process<ConnectionMessage>,
+    stream_id: u32,
+    stream: Stream,
+    handler: T,
+    config: ServerConfig,
+    head: RequestHead,
+}
+
+/// Handles a single HTTP/2 stream and sends the response back to the
+/// connection process.
+fn stream_worker<T, Arg, Ret>(stream_request: StreamRequest<T>, mailbox: Mailbox<()>)
+where
+    T: Handler<Arg, Ret> + Serialize + DeserializeOwned,
+{
+    let StreamRequest {
+        connection,
+        stream_id,
+        stream,
+        handler,
+        config,
+        head,
+    } = stream_request;
+    let request_line = Arc::new(Mutex::new(Some(format!(
+        "{} {} HTTP/2.0",
+        head.method, head.uri
+    ))));
+    set_panic_hook(request_line, move |panic| {
+        connection.send(ConnectionMessage::Panicked(stream_id, panic));
+    });
+
+    let info = head.info();
+    let request = match head.into_request() {
+        Ok(request) => request,
+        Err(err) => {
+            let failure = Failure::new(FailureKind::InvalidRequest, StatusCode::BAD_REQUEST)
+                .with_request(Some(&info))
+                .with_message(err);
+            connection.send(ConnectionMessage::Failure(stream_id, failure));
+            return;
+        }
+    };
+    log_request(&request);
+
+    let is_head = request.method() == http::Method::HEAD;
+    let mut response = Handler::handle(&handler, RequestContext::new(request, stream));
+    let stream_body = match response.extensions_mut().remove::<StreamBody>() {
+        Some(stream_body) => stream_body,
+        None => {
+            connection.send(ConnectionMessage::Response(StreamResponse::from_response(
+                stream_id, response, &config, is_head,
+            )));
+            return;
+        }
+    };
+    // The length of a streaming body is only known once it was sent, so a
+    // length set by the handler is dropped. Each chunk is sent as DATA frames by
+    // the connection process as soon as it's produced.
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    insert_server_headers(response.headers_mut(), &config);
+    connection.send(ConnectionMessage::Response(StreamResponse {
+        stream_id,
+        status: response.status().as_u16(),
+        headers: header_fields(response.headers()),
+        body: Vec::new(),
+        streaming: !is_head,
+    }));
+    if is_head {
+        return;
+    }
+    let mut writer = DataWriter {
+        connection,
+        stream_id,
+        mailbox,
+    };
+    // Writing to the connection process can't fail
+    let _ = stream_body.write_to(&mut writer, false);
+    connection.send(ConnectionMessage::Data(StreamData {
+        stream_id,
+        data: Vec::new(),
+        end_stream: true,
+    }));
+}
+
+/// Renders the response to a failure of a stream with the error handler.
+fn render_stream_failure(
+    (stream_id, failure, error_handler, config, connection): (
+        u32,
+        Failure,
+        ErrorHandler,
+        ServerConfig,
+        Process<ConnectionMessage>,
+    ),
+    _: Mailbox<()>,
+) {
+    let is_head = failure.method() == Some("HEAD");
+    let response = (error_handler.get())(failure);
+    connection.send(ConnectionMessage::Response(StreamResponse::from_response(
+        stream_id, response, &config, is_head,
+    )));
+}
+
+/// Reads frames from the TCP stream and sends them to the connection process.
+fn frame_reader(
+    (stream, connection, next, expect_preface): (Stream, Process<ConnectionMessage>, Vec<u8>, bool),
+    _: Mailbox<()>,
+) {
+    let mut reader = Cursor::new(next).chain(stream);
+    if expect_preface {
+        let mut preface = [0_u8; PREFACE.len()];
+        if reader.read_exact(&mut preface).is_err() || preface != PREFACE {
+            connection.send(ConnectionMessage::ReadFailed(Some(PROTOCOL_ERROR)));
+            return;
+        }
+    }
+    loop {
+        let mut header = [0_u8; FRAME_HEADER_LEN];
+        if reader.read_exact(&mut header).is_err() {
+            connection.send(ConnectionMessage::ReadFailed(None));
+            return;
+        }
+        let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        if len > DEFAULT_MAX_FRAME_SIZE {
+            connection.send(ConnectionMessage::ReadFailed(Some(FRAME_SIZE_ERROR)));
+            return;
+        }
+        let mut payload = vec![0_u8; len];
+        if reader.read_exact(&mut payload).is_err() {
+            connection.send(ConnectionMessage::ReadFailed(None));
+            return;
+        }
+        let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+        connection.send(ConnectionMessage::Frame(Frame {
+            kind: header[3],
+            flags: header[4],
+            // The reserved bit is ignored
+            stream_id: stream_id & 0x7fff_ffff,
+            payload,
+        }));
+    }
+}
+
+/// Handles an HTTP/2 connection until it's closed, notifying the supervisor
+/// at the end.
+pub(crate) fn connection<T, Arg, Ret>(
+    (stream, handler, config, worker_config, error_handler, supervisor, start): (
+        Stream,
+        T,
+        ServerConfig,
+        Option<ProcessConfig>,
+        Option<ErrorHandler>,
+        SupervisorHandle,
+        Http2Start,
+    ),
+    mailbox: Mailbox<ConnectionMessage>,
+) where
+    T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+{
+    let this = mailbox.this();
+    // Failure in linked worker processes should not kill the connection
+    let mailbox = mailbox.catch_link_failure();
+    let mut conn = Http2Connection::new(
+        this.clone(),
+        stream.clone(),
+        config.clone(),
+        worker_config,
+        error_handler,
+    );
+
+    let expect_preface = start.upgrade.is_some();
+    let result = conn.start(start.upgrade, &handler);
+    let reader = Process::spawn_link(
+        (stream, this.clone(), start.next, expect_preface),
+        frame_reader,
+    );
+
+    let mut result = result.and_then(|()| loop {
+        // Without any streams the connection is idle, otherwise the workers are
+        // checked for timeouts.
+        let timeout = if conn.workers.is_empty() {
+            config.keep_alive_timeout
+        } else {
+            Duration::from_secs(1).min(config.request_timeout)
+        };
+        let result = match mailbox.receive_timeout(timeout) {
+            Ok(MessageSignal::Message(ConnectionMessage::Frame(frame))) => {
+                conn.handle_frame(frame, &handler)
+            }
+            Ok(MessageSignal::Message(ConnectionMessage::Response(response))) => {
+                conn.send_response(response)
+            }
+            Ok(MessageSignal::Message(ConnectionMessage::Data(data))) => conn.send_data(data),
+            Ok(MessageSignal::Message(ConnectionMessage::Failure(stream_id, failure))) => {
+                conn.send_failure(stream_id, failure)
+            }
+            Ok(MessageSignal::Message(ConnectionMessage::Panicked(stream_id, panic))) => {
+                conn.worker_panicked(stream_id, panic);
+                Ok(())
+            }
+            Ok(MessageSignal::Message(ConnectionMessage::ReadFailed(Some(code)))) => {
+                Err(Close::GoAway(code))
+            }
+            Ok(MessageSignal::Message(ConnectionMessage::ReadFailed(None))) => Err(Close::Io),
+            Ok(MessageSignal::Message(ConnectionMessage::Shutdown)) => conn.shutdown(),
+            Ok(MessageSignal::Signal(tag)) => conn.worker_failed(tag),
+            Err(MailboxError::TimedOut) if conn.workers.is_empty() && conn.streams.is_empty() => {
+                Err(Close::GoAway(NO_ERROR))
+            }
+            Err(MailboxError::TimedOut) => conn.check_timeouts(),
+            Err(MailboxError::DeserializationFailed(err)) => {
+                panic!("failed to deserialize http/2 connection message: {err}");
+            }
+        };
+        if result.is_err() {
+            break result;
+        }
+        // After the client went away or the server started shutting down, finish the
+        // remaining streams and close
+        if (conn.go_away_received || conn.shutting_down) && conn.streams.is_empty() {
+            break Err(Close::GoAway(NO_ERROR));
+        }
+    });
+    if let Err(Close::GoAway(code)) = result {
+        result = conn.go_away(code);
+    }
+    if let Err(Close::Io) = result {
+        log_error("Failed to write to http/2 connection");
+    }
+    reader.unlink();
+    reader.kill();
+    for worker in conn.workers {
+        worker.process.unlink();
+        worker.process.kill();
+    }
+    supervisor.send(WorkerResponse::Http2Closed);
+}
+
+/// Reason for closing the connection.
+enum Close {
+    /// Close the connection with a GOAWAY frame containing the error code.
+    GoAway(u32),
+    /// The TCP stream failed.
+    Io,
+}
+
+impl From<io::Error> for Close {
+    fn from(_: io::Error) -> Self {
+        Close::Io
+    }
+}
+
+struct Http2Connection {
+    this: Process<ConnectionMessage>,
+    stream: Stream,
+    config: ServerConfig,
+    worker_config: Option<ProcessConfig>,
+    error_handler: Option<ErrorHandler>,
+    decoder: hpack::Decoder<'static>,
+    encoder: hpack::Encoder<'static>,
+    streams: HashMap<u32, StreamState>,
+    workers: Vec<Worker>,
+    /// A header block waiting for CONTINUATION frames.
+    continuation: Option<HeaderBlock>,
+    last_stream_id: u32,
+    /// The connection window for sending data.
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame_size: usize,
+    go_away_received: bool,
+    /// The server is shutting down and sent a GOAWAY frame.
+    shutting_down: bool,
+}
+
+struct StreamState {
+    /// The request, until it's dispatched to a worker.
+    head: Option<RequestHead>,
+    /// The client finished sending the request.
+    received: bool,
+    /// The stream window for sending data.
+    send_window: i64,
+    /// The response body waiting for the send window.
+    pending: Option<Vec<u8>>,
+    /// How much of the pending body was sent.
+    sent: usize,
+    /// The pending body is the end of the response. Streaming bodies are
+    /// pending one chunk at a time.
+    complete: bool,
+    /// The worker waits for the pending chunk to be sent.
+    awaiting_ack: bool,
+}
+
+struct HeaderBlock {
+    stream_id: u32,
+    end_stream: bool,
+    fragment: Vec<u8>,
+}
+
+struct Worker {
+    tag: Tag,
+    stream_id: u32,
+    process: Process<()>,
+    started: Instant,
+    /// The request being handled, if it was valid.
+    request: Option<RequestInfo>,
+    /// The panic reported by the worker before it failed.
+    panicked: Option<WorkerPanic>,
+    /// The failure the process renders with the error handler, if it's not
+    /// handling a request.
+    failure: Option<Failure>,
+    /// The worker sent the response head and is streaming the body.
+    streaming: bool,
+}
+
+impl Http2Connection {
+    fn new(
+        this: Process<ConnectionMessage>,
+        stream: Stream,
+        config: ServerConfig,
+        worker_config: Option<ProcessConfig>,
+        error_handler: Option<ErrorHandler>,
+    ) -> Self {
+        Http2Connection {
+            this,
+            stream,
+            config,
+            worker_config,
+            error_handler,
+            decoder: hpack::Decoder::new(),
+            encoder: hpack::Encoder::new(),
+            streams: HashMap::new(),
+            workers: Vec::new(),
+            continuation: None,
+            last_stream_id: 0,
+            send_window: DEFAULT_WINDOW_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            go_away_received: false,
+            shutting_down: false,
+        }
+    }
+
+    /// Sends the server preface, and dispatches the upgraded request.
+    fn start<T, Arg, Ret>(&mut self, upgrade: Option<Upgrade>, handler: &T) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        if upgrade.is_some() {
+            self.stream.write_all(SWITCHING_PROTOCOLS)?;
+        }
+        let mut settings = Vec::new();
+        for (id, value) in [
+            (
+                SETTINGS_MAX_CONCURRENT_STREAMS,
+                MAX_CONCURRENT_STREAMS as u32,
+            ),
+            (
+                SETTINGS_MAX_HEADER_LIST_SIZE,
+                self.config.max_headers_size as u32,
+            ),
+        ] {
+            settings.extend(id.to_be_bytes());
+            settings.extend(value.to_be_bytes());
+        }
+        write_frame(&mut self.stream, SETTINGS, 0, 0, &settings)?;
+
+        if let Some(upgrade) = upgrade {
+            self.apply_settings(&upgrade.settings)?;
+            // The upgraded request is stream 1, and the client already sent it.
+            self.last_stream_id = 1;
+            self.streams.insert(
+                1,
+                StreamState {
+                    head: Some(upgrade.head),
+                    received: true,
+                    send_window: self.peer_initial_window,
+                    pending: None,
+                    sent: 0,
+                    complete: false,
+                    awaiting_ack: false,
+                },
+            );
+            self.dispatch(1, handler);
+        }
+        Ok(())
+    }
+
+    fn handle_frame<T, Arg, Ret>(&mut self, frame: Frame, handler: &T) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        // A header block must be continued without other frames in between
+        if let Some(continuation) = &self.continuation {
+            if frame.kind != CONTINUATION || frame.stream_id != continuation.stream_id {
+                return Err(Close::GoAway(PROTOCOL_ERROR));
+            }
+        }
+        match frame.kind {
+            DATA => self.handle_data(frame, handler),
+            HEADERS => self.handle_headers(frame, handler),
+            CONTINUATION => self.handle_continuation(frame, handler),
+            PRIORITY => Ok(()),
+            RST_STREAM => {
+                self.streams.remove(&frame.stream_id);
+                // The client doesn't want the response anymore
+                for worker in self.finish_workers(frame.stream_id) {
+                    worker.process.kill();
+                }
+                Ok(())
+            }
+            SETTINGS => {
+                if frame.stream_id != 0 {
+                    return Err(Close::GoAway(PROTOCOL_ERROR));
+                }
+                if frame.flags & ACK != 0 {
+                    return Ok(());
+                }
+                self.apply_settings(&frame.payload)?;
+                write_frame(&mut self.stream, SETTINGS, ACK, 0, &[])?;
+                Ok(())
+            }
+            PING => {
+                if frame.payload.len() != 8 {
+                    return Err(Close::GoAway(FRAME_SIZE_ERROR));
+                }
+                if frame.flags & ACK == 0 {
+                    write_frame(&mut self.stream, PING, ACK, 0, &frame.payload)?;
+                }
+                Ok(())
+            }
+            GOAWAY => {
+                self.go_away_received = true;
+                Ok(())
+            }
+            WINDOW_UPDATE => self.handle_window_update(frame),
+            // Clients can't push streams
+            PUSH_PROMISE => Err(Close::GoAway(PROTOCOL_ERROR)),
+            // Unknown frames are ignored
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_data<T, Arg, Ret>(&mut self, frame: Frame, handler: &T) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let data = strip_padding(frame.flags, &frame.payload)?;
+        // Flow control counts the whole frame, including padding. The data is
+        // buffered right away, so the window is restored immediately.
+        let window_increment = (frame.payload.len() as u32).to_be_bytes();
+        if !frame.payload.is_empty() {
+            write_frame(&mut self.stream, WINDOW_UPDATE, 0, 0, &window_increment)?;
+        }
+        let stream_id = frame.stream_id;
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) if !stream.received => stream,
+            // Streams started after the GOAWAY frame are ignored
+            _ if self.shutting_down && stream_id > self.last_stream_id => return Ok(()),
+            _ if stream_id == 0 || stream_id > self.last_stream_id => {
+                return Err(Close::GoAway(PROTOCOL_ERROR));
+            }
+            _ => return self.reset_stream(stream_id, STREAM_CLOSED),
+        };
+        let end_stream = frame.flags & END_STREAM != 0;
+        stream.received = end_stream;
+        let mut too_large = None;
+        if let Some(head) = &mut stream.head {
+            if head.body.len() + data.len() > self.config.max_body_size {
+                too_large = Some(head.info());
+                stream.head = None;
+            } else {
+                head.body.extend(data);
+            }
+        }
+        if !end_stream && !frame.payload.is_empty() {
+            write_frame(
+                &mut self.stream,
+                WINDOW_UPDATE,
+                0,
+                stream_id,
+                &window_increment,
+            )?;
+        }
+        if let Some(request) = too_large {
+            let failure = Failure::new(FailureKind::InvalidRequest, StatusCode::PAYLOAD_TOO_LARGE)
+                .with_request(Some(&request));
+            return self.send_failure(stream_id, failure);
+        }
+        if end_stream {
+            self.dispatch(stream_id, handler);
+        }
+        Ok(())
+    }
+
+    fn handle_headers<T, Arg, Ret>(&mut self, frame: Frame, handler: &T) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let mut fragment = strip_padding(frame.flags, &frame.payload)?;
+        if frame.flags & PRIORITY_FLAG != 0 {
+            if fragment.len() < 5 {
+                return Err(Close::GoAway(FRAME_SIZE_ERROR));
+            }
+            fragment = &fragment[5..];
+        }
+        let block = HeaderBlock {
+            stream_id: frame.stream_id,
+            end_stream: frame.flags & END_STREAM != 0,
+            fragment: fragment.to_vec(),
+        };
+        if frame.flags & END_HEADERS == 0 {
+            self.continuation = Some(block);
+            Ok(())
+        } else {
+            self.handle_header_block(block, handler)
+        }
+    }
+
+    fn handle_continuation<T, Arg, Ret>(&mut self, frame: Frame, handler: &T) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let mut block = match self.continuation.take() {
+            Some(block) => block,
+            None => return Err(Close::GoAway(PROTOCOL_ERROR)),
+        };
+        block.fragment.extend(&frame.payload);
+        if block.fragment.len() > self.config.max_headers_size {
+            return Err(Close::GoAway(ENHANCE_YOUR_CALM));
+        }
+        if frame.flags & END_HEADERS == 0 {
+            self.continuation = Some(block);
+            Ok(())
+        } else {
+            self.handle_header_block(block, handler)
+        }
+    }
+
+    fn handle_header_block<T, Arg, Ret>(
+        &mut self,
+        block: HeaderBlock,
+        handler: &T,
+    ) -> Result<(), Close>
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        // The block is always decoded to keep the decoder state in sync
+        let fields = self
+            .decoder
+            .decode(&block.fragment)
+            .map_err(|_| Close::GoAway(COMPRESSION_ERROR))?;
+        let stream_id = block.stream_id;
+        if stream_id % 2 == 0 {
+            return Err(Close::GoAway(PROTOCOL_ERROR));
+        }
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            // Trailers end the request and are ignored
+            if stream.received || !block.end_stream {
+                return Err(Close::GoAway(PROTOCOL_ERROR));
+            }
+            stream.received = true;
+            self.dispatch(stream_id, handler);
+            return Ok(());
+        }
+        if stream_id <= self.last_stream_id {
+            return self.reset_stream(stream_id, STREAM_CLOSED);
+        }
+        // Streams started after the GOAWAY frame are ignored
+        if self.shutting_down {
+            return Ok(());
+        }
+        self.last_stream_id = stream_id;
+        if self.streams.len() >= MAX_CONCURRENT_STREAMS {
+            return self.reset_stream(stream_id, REFUSED_STREAM);
+        }
+        let (head, error) = match RequestHead::from_fields(fields, &self.config) {
+            Ok(head) => (Some(head), None),
+            Err(status) => (None, Some(status)),
+        };
+        self.streams.insert(
+            stream_id,
+            StreamState {
+                head,
+                received: block.end_stream,
+                send_window: self.peer_initial_window,
+                pending: None,
+                sent: 0,
+                complete: false,
+                awaiting_ack: false,
+            },
+        );
+        if let Some(status) = error {
+            let failure = Failure::new(FailureKind::InvalidRequest, status);
+            return self.send_failure(stream_id, failure);
+        }
+        if block.end_stream {
+            self.dispatch(stream_id, handler);
+        }
+        Ok(())
+    }
+
+    fn handle_window_update(&mut self, frame: Frame) -> Result<(), Close> {
+        if frame.payload.len() != 4 {
+            return Err(Close::GoAway(FRAME_SIZE_ERROR));
+        }
+        let increment = u32::from_be_bytes([
+            frame.payload[0],
+            frame.payload[1],
+            frame.payload[2],
+            frame.payload[3],
+        ]) & 0x7fff_ffff;
+        if increment == 0 {
+            return Err(Close::GoAway(PROTOCOL_ERROR));
+        }
+        if frame.stream_id == 0 {
+            self.send_window += increment as i64;
+            if self.send_window > MAX_WINDOW_SIZE {
+                return Err(Close::GoAway(FLOW_CONTROL_ERROR));
+            }
+            self.flush_all()
+        } else if let Some(stream) = self.streams.get_mut(&frame.stream_id) {
+            stream.send_window += increment as i64;
+            if stream.send_window > MAX_WINDOW_SIZE {
+                return Err(Close::GoAway(FLOW_CONTROL_ERROR));
+            }
+            self.flush(frame.stream_id)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<(), Close> {
+        if payload.len() % 6 != 0 {
+            return Err(Close::GoAway(FRAME_SIZE_ERROR));
+        }
+        for setting in payload.chunks(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(Close::GoAway(PROTOCOL_ERROR));
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW_SIZE {
+                        return Err(Close::GoAway(FLOW_CONTROL_ERROR));
+                    }
+                    // The change applies to the windows of all open streams
+                    let delta = value as i64 - self.peer_initial_window;
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                    }
+                    self.peer_initial_window = value as i64;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16_384..=16_777_215).contains(&value) {
+                        return Err(Close::GoAway(PROTOCOL_ERROR));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                _ => {}
+            }
+        }
+        self.flush_all()
+    }
+
+    /// Spawns a worker for the stream, once the whole request was received.
+    fn dispatch<T, Arg, Ret>(&mut self, stream_id: u32, handler: &T)
+    where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let head = match self.streams.get_mut(&stream_id) {
+            Some(stream) if stream.received => stream.head.take(),
+            _ => None,
+        };
+        let head = match head {
+            Some(head) => head,
+            None => return,
+        };
+        let request = head.info();
+        let stream_request = StreamRequest {
+            connection: self.this.clone(),
+            stream_id,
+            stream: self.stream.clone(),
+            handler: handler.clone(),
+            config: self.config.clone(),
+            head,
+        };
+        let tag = Tag::new();
+        let process = match &self.worker_config {
+            Some(worker_config) => Process::spawn_link_config_tag(
+                worker_config,
+                stream_request,
+                tag,
+                stream_worker::<T, Arg, Ret>,
+            ),
+            None => Process::spawn_link_tag(stream_request, tag, stream_worker::<T, Arg, Ret>),
+        };
+        self.workers.push(Worker {
+            tag,
+            stream_id,
+            process,
+            started: Instant::now(),
+            request: Some(request),
+            panicked: None,
+            failure: None,
+            streaming: false,
+        });
+    }
+
+    fn worker_panicked(&mut self, stream_id: u32, panic: WorkerPanic) {
+        if let Some(worker) = self
+            .workers
+            .iter_mut()
+            .find(|worker| worker.stream_id == stream_id)
+        {
+            worker.panicked = Some(panic);
+        }
+    }
+
+    fn worker_failed(&mut self, tag: Tag) -> Result<(), Close> {
+        let index = match self.workers.iter().position(|worker| worker.tag == tag) {
+            Some(index) => index,
+            // Finished workers are unlinked, so the failed process is the frame reader
+            // or the supervisor, and the connection can't continue without them.
+            None => return Err(Close::GoAway(INTERNAL_ERROR)),
+        };
+        let worker = self.workers.remove(index);
+        if worker.streaming {
+            // Part of the response was already sent, it can only be cut off
+            log_error("Worker process failed while streaming the response");
+            return self.reset_stream(worker.stream_id, INTERNAL_ERROR);
+        }
+        if let Some(failure) = worker.failure {
+            log_error("Error handler panicked");
+            let response = StreamResponse::from_failure(worker.stream_id, &failure, &self.config);
+            return self.send_response(response);
+        }
+        let failure = worker_failure(worker.panicked.as_ref(), self.worker_config.is_some())
+            .with_request(worker.request.as_ref());
+        self.send_failure(worker.stream_id, failure)
+    }
+
+    fn check_timeouts(&mut self) -> Result<(), Close> {
+        let (timed_out, workers) = std::mem::take(&mut self.workers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|worker| {
+                worker.started.elapsed() > self.config.request_timeout
+            });
+        self.workers = workers;
+        for worker in timed_out {
+            worker.process.unlink();
+            worker.process.kill();
+            if worker.streaming {
+                log_error("Streaming the response timed out");
+                self.reset_stream(worker.stream_id, INTERNAL_ERROR)?;
+                continue;
+            }
+            match worker.failure {
+                Some(failure) => {
+                    log_error("Error handler failed to render the response");
+                    let response =
+                        StreamResponse::from_failure(worker.stream_id, &failure, &self.config);
+                    self.send_response(response)?;
+                }
+                None => {
+                    log_error("Request timed out");
+                    let failure =
+                        Failure::new(FailureKind::RequestTimeout, StatusCode::REQUEST_TIMEOUT)
+                            .with_request(worker.request.as_ref());
+                    self.send_failure(worker.stream_id, failure)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the workers of the stream, which don't concern the connection
+    /// anymore.
+    fn finish_workers(&mut self, stream_id: u32) -> Vec<Worker> {
+        let (finished, workers) = std::mem::take(&mut self.workers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|worker| worker.stream_id == stream_id);
+        self.workers = workers;
+        for worker in &finished {
+            worker.process.unlink();
+        }
+        finished
+    }
+
+    /// Responds to a failure, using the error handler if there is one. The
+    /// error handler runs in a separate process, which is tracked like a
+    /// worker, so it can't take down the connection.
+    fn send_failure(&mut self, stream_id: u32, failure: Failure) -> Result<(), Close> {
+        self.finish_workers(stream_id);
+        let error_handler = match &self.error_handler {
+            Some(error_handler) => error_handler.clone(),
+            None => {
+                let response = StreamResponse::from_failure(stream_id, &failure, &self.config);
+                return self.send_response(response);
+            }
+        };
+        let tag = Tag::new();
+        let process = Process::spawn_link_tag(
+            (
+                stream_id,
+                failure.clone(),
+                error_handler,
+                self.config.clone(),
+                self.this.clone(),
+            ),
+            tag,
+            render_stream_failure,
+        );
+        self.workers.push(Worker {
+            tag,
+            stream_id,
+            process,
+            started: Instant::now(),
+            request: None,
+            panicked: None,
+            failure: Some(failure),
+            streaming: false,
+        });
+        Ok(())
+    }
+
+    fn send_response(&mut self, response: StreamResponse) -> Result<(), Close> {
+        let stream_id = response.stream_id;
+        if response.streaming {
+            if let Some(worker) = self
+                .workers
+                .iter_mut()
+                .find(|worker| worker.stream_id == stream_id)
+            {
+                worker.streaming = true;
+            }
+        } else {
+            self.finish_workers(stream_id);
+        }
+        // The client might have reset the stream in the meantime
+        match self.streams.get(&stream_id) {
+            Some(stream) if stream.pending.is_none() => {}
+            _ => return Ok(()),
+        }
+
+        let status = response.status.to_string();
+        let mut fields: Vec<(&[u8], &[u8])> = vec![(b":status", status.as_bytes())];
+        fields.extend(
+            response
+                .headers
+                .iter()
+                .filter(|(name, _)| !is_connection_specific(name))
+                .map(|(name, value)| (name.as_bytes(), value.as_slice())),
+        );
+        let block = self.encoder.encode(fields);
+        let end_stream = response.body.is_empty() && !response.streaming;
+        let mut fragments = block.chunks(self.peer_max_frame_size).peekable();
+        let mut kind = HEADERS;
+        while let Some(fragment) = fragments.next() {
+            let mut flags = 0;
+            if kind == HEADERS && end_stream {
+                flags |= END_STREAM;
+            }
+            if fragments.peek().is_none() {
+                flags |= END_HEADERS;
+            }
+            write_frame(&mut self.stream, kind, flags, stream_id, fragment)?;
+            kind = CONTINUATION;
+        }
+
+        if end_stream {
+            self.streams.remove(&stream_id);
+            return Ok(());
+        }
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.pending = Some(response.body);
+            stream.complete = !response.streaming;
+        }
+        self.flush(stream_id)
+    }
+
+    /// Queues a chunk of a streaming response body.
+    fn send_data(&mut self, data: StreamData) -> Result<(), Close> {
+        let stream_id = data.stream_id;
+        if data.end_stream {
+            self.finish_workers(stream_id);
+        }
+        // The client might have reset the stream in the meantime
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        let pending = stream.pending.get_or_insert_with(Vec::new);
+        pending.drain(..stream.sent);
+        pending.extend(data.data);
+        stream.sent = 0;
+        stream.complete = data.end_stream;
+        stream.awaiting_ack = !data.end_stream;
+        self.flush(stream_id)
+    }
+
+    /// Sends as much of the pending response body as the flow control windows
+    /// allow.
+    fn flush(&mut self, stream_id: u32) -> Result<(), Close> {
+        let stream = match self.streams.get_mut(&stream_id) {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        let body = match &stream.pending {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+        while stream.sent < body.len() {
+            let window = self.send_window.min(stream.send_window);
+            if window <= 0 {
+                return Ok(());
+            }
+            let len = (body.len() - stream.sent)
+                .min(window as usize)
+                .min(self.peer_max_frame_size);
+            let end = stream.sent + len;
+            let flags = if end == body.len() && stream.complete {
+                END_STREAM
+            } else {
+                0
+            };
+            write_frame(
+                &mut self.stream,
+                DATA,
+                flags,
+                stream_id,
+                &body[stream.sent..end],
+            )?;
+            stream.sent = end;
+            stream.send_window -= len as i64;
+            self.send_window -= len as i64;
+        }
+        if !stream.complete {
+            // The worker produces the next chunk once the previous one was sent
+            if stream.awaiting_ack {
+                stream.awaiting_ack = false;
+                if let Some(worker) = self
+                    .workers
+                    .iter()
+                    .find(|worker| worker.stream_id == stream_id)
+                {
+                    worker.process.send(());
+                }
+            }
+            return Ok(());
+        }
+        // A streaming body ends with an empty frame after the last chunk
+        if body.is_empty() {
+            write_frame(&mut self.stream, DATA, END_STREAM, stream_id, &[])?;
+        }
+        self.streams.remove(&stream_id);
+        Ok(())
+    }
+
+    fn flush_all(&mut self) -> Result<(), Close> {
+        let stream_ids: Vec<u32> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| stream.pending.is_some())
+            .map(|(stream_id, _)| *stream_id)
+            .collect();
+        for stream_id in stream_ids {
+            self.flush(stream_id)?;
+        }
+        Ok(())
+    }
+
+    /// Stops accepting new streams, letting the client know with a GOAWAY
+    /// frame. The connection is closed once the active streams are
+    /// finished.
+    fn shutdown(&mut self) -> Result<(), Close> {
+        self.shutting_down = true;
+        self.go_away(NO_ERROR)
+    }
+
+    /// Sends a GOAWAY frame with the last stream that was started.
+    fn go_away(&mut self, code: u32) -> Result<(), Close> {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend(code.to_be_bytes());
+        write_frame(&mut self.stream, GOAWAY, 0, 0, &payload)?;
+        Ok(())
+    }
+
+    fn reset_stream(&mut self, stream_id: u32, code: u32) -> Result<(), Close> {
+        self.streams.remove(&stream_id);
+        write_frame(
+            &mut self.stream,
+            RST_STREAM,
+            0,
+            stream_id,
+            &code.to_be_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Converts the headers of a response to be sent to the connection process.
+fn header_fields(headers: &HeaderMap) -> Vec<(String, Vec<u8>)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+        .collect()
+}
+
+/// Removes the padding of DATA and HEADERS frames.
+fn strip_padding(flags: u8, payload: &[u8]) -> Result<&[u8], Close> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    let (pad_len, rest) = match payload.split_first() {
+        Some((pad_len, rest)) => (*pad_len as usize, rest),
+        None => return Err(Close::GoAway(FRAME_SIZE_ERROR)),
+    };
+    if pad_len > rest.len() {
+        return Err(Close::GoAway(PROTOCOL_ERROR));
+    }
+    Ok(&rest[..(rest.len() - pad_len)])
+}
+
+fn write_frame(
+    stream: &mut Stream,
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend(&(payload.len() as u32).to_be_bytes()[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend(stream_id.to_be_bytes());
+    frame.extend(payload);
+    stream.write_all(&frame)
+}
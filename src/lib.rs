@@ -61,14 +61,17 @@ pub use submillisecond_macros::*;
 pub use {headers, http};
 
 pub use crate::app::Application;
+pub use crate::config::*;
 pub use crate::core::Body;
 pub use crate::error::*;
+pub use crate::failure::{Failure, FailureKind};
 pub use crate::guard::*;
 pub use crate::handler::*;
 #[cfg(feature = "json")]
 pub use crate::json::*;
 pub use crate::request::*;
-use crate::response::Response;
+pub use crate::server::ShutdownHandle;
+pub use crate::stream::*;
 pub use crate::typed_header::*;
 
 #[macro_use]
@@ -81,8 +84,11 @@ pub mod extract;
 pub mod params;
 pub mod reader;
 pub mod response;
+pub mod routes;
 #[cfg(feature = "cookies")]
 pub mod session;
+#[cfg(feature = "sse")]
+pub mod sse;
 pub mod state;
 #[cfg(feature = "template")]
 pub mod template;
@@ -90,15 +96,21 @@ pub mod template;
 pub mod websocket;
 
 mod app;
+mod config;
 mod core;
 mod error;
+mod failure;
 mod guard;
 mod handler;
+#[cfg(feature = "http2")]
+mod http2;
 #[cfg(feature = "json")]
 mod json;
 mod request;
+mod server;
+mod stream;
 mod supervisor;
 mod typed_header;
 
 /// Signature of router function generated by the [`router!`] macro.
-pub type Router = fn() -> fn(RequestContext) -> Response;
+pub type Router = fn() -> RouterHandler;
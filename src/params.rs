@@ -5,6 +5,22 @@
 
 use std::{iter, mem, slice};
 
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+// Characters which are percent-encoded in a path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
 /// A single URL parameter, consisting of a key and a value.
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Default, Clone)]
 pub struct Param {
@@ -125,6 +141,38 @@ impl Params {
             ParamsKind::Large(vec) => vec.push(param),
         }
     }
+
+    /// Shortens the list, keeping the first `len` parameters.
+    ///
+    /// The [`router!`](crate::router) macro uses this to remove the params of
+    /// a route which didn't match.
+    pub fn truncate(&mut self, len: usize) {
+        match &mut self.kind {
+            ParamsKind::None => {}
+            ParamsKind::Small(arr, small_len) => {
+                if len < *small_len {
+                    arr[len..*small_len].iter_mut().for_each(|param| {
+                        mem::take(param);
+                    });
+                    *small_len = len;
+                }
+            }
+            ParamsKind::Large(vec) => vec.truncate(len),
+        }
+    }
+}
+
+/// Percent-encode a param value for a url path, including any `/`.
+///
+/// The named routes of the [`router!`](crate::router) macro use this to fill in
+/// params.
+pub fn encode(value: &str) -> String {
+    utf8_percent_encode(value, SEGMENT).to_string()
+}
+
+/// Percent-encode a wildcard value for a url path, keeping any `/`.
+pub fn encode_path(value: &str) -> String {
+    value.split('/').map(encode).collect::<Vec<_>>().join("/")
 }
 
 /// An iterator over the keys and values of a route's [parameters](Params).
@@ -212,6 +260,29 @@ mod tests {
         assert!(params.iter().eq(vec.clone()));
     }
 
+    #[test]
+    fn truncate() {
+        let mut params = Params::new();
+        params.push("hello", "hello".to_string());
+        params.push("world", "world".to_string());
+        params.truncate(1);
+        assert!(params.iter().eq(vec![("hello", "hello")]));
+
+        for key in ["foo", "bar", "baz"] {
+            params.push(key, key.to_string());
+        }
+        params.truncate(2);
+        assert!(params.iter().eq(vec![("hello", "hello"), ("foo", "foo")]));
+    }
+
+    #[test]
+    fn encode_params() {
+        assert_eq!(encode("hello world"), "hello%20world");
+        assert_eq!(encode("a/b?c"), "a%2Fb%3Fc");
+        assert_eq!(encode("café"), "caf%C3%A9");
+        assert_eq!(encode_path("docs/read me.md"), "docs/read%20me.md");
+    }
+
     #[test]
     fn ignore_array_default() {
         let params = Params::new();
@@ -2,6 +2,9 @@
 //!
 //! The [`router!`](crate::router) macro uses this internally for routing.
 
+use once_cell::sync::OnceCell;
+use regex::Regex;
+
 /// A uri string and cursor reader.
 #[derive(Clone, Debug, Default)]
 pub struct UriReader {
@@ -117,9 +120,38 @@ impl UriReader {
     }
 }
 
+/// A regular expression compiled on first use.
+///
+/// The [`router!`](crate::router) macro generates a static pattern for each
+/// param with a regular expression constraint, eg. `:slug<[a-z-]+>`, so the
+/// pattern is compiled once per process.
+#[derive(Debug)]
+pub struct Pattern {
+    pattern: &'static str,
+    regex: OnceCell<Option<Regex>>,
+}
+
+impl Pattern {
+    /// Creates a pattern from a regular expression, without compiling it.
+    pub const fn new(pattern: &'static str) -> Self {
+        Pattern {
+            pattern,
+            regex: OnceCell::new(),
+        }
+    }
+
+    /// Check if `value` matches the pattern. Invalid patterns never match.
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex
+            .get_or_init(|| Regex::new(self.pattern).ok())
+            .as_ref()
+            .map_or(false, |regex| regex.is_match(value))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::UriReader;
+    use super::{Pattern, UriReader};
 
     #[test]
     fn peek_empty_string() {
@@ -137,4 +169,13 @@ mod tests {
         assert_eq!(reader.peek(3), "");
         reader.read(3);
     }
+
+    #[test]
+    fn match_pattern() {
+        let pattern = Pattern::new("^(?:[a-z-]+)$");
+        assert!(pattern.is_match("hello-world"));
+        assert!(!pattern.is_match("hello/world"));
+        assert!(!pattern.is_match("Hello"));
+        assert!(!Pattern::new("[a-z").is_match("a"));
+    }
 }
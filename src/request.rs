@@ -1,11 +1,15 @@
+use std::cell::RefCell;
+use std::io::{self, Read};
+use std::rc::Rc;
 use std::{convert, ops};
 
-use lunatic::net::TcpStream;
+use once_cell::unsync::OnceCell;
 
-use crate::core::Body;
+use crate::core::{self, Body, BodyReader};
+use crate::extract::rejection::{FailedToBufferBody, LengthLimitError, UnknownBodyError};
 use crate::params::Params;
 use crate::reader::UriReader;
-use crate::Response;
+use crate::{Response, Stream};
 
 /// Wrapper for [`http::Request`] containing params and cursor.
 pub struct RequestContext {
@@ -19,24 +23,97 @@ pub struct RequestContext {
     ///
     /// This is useful for middleware. See [`RequestContext::next_handler`].
     pub(crate) next: Option<fn(RequestContext) -> Response>,
-    /// The TCP stream.
-    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
-    pub(crate) stream: TcpStream,
+    /// The TCP or TLS stream.
+    pub(crate) stream: Stream,
+    /// Reader for the part of the body that wasn't read yet.
+    ///
+    /// It's shared with the worker, which consumes the rest of the body after
+    /// the handler to find where the next request starts.
+    pub(crate) body_reader: Option<Rc<RefCell<BodyReader>>>,
+    /// The rest of the body, once it was read by [`RequestContext::body`].
+    buffered_body: OnceCell<io::Result<Body<'static>>>,
 }
 
 impl RequestContext {
     /// Creates a new instance of request context.
-    pub fn new(request: http::Request<Body<'static>>, stream: TcpStream) -> Self {
+    pub fn new(request: http::Request<Body<'static>>, stream: impl Into<Stream>) -> Self {
         let path = request.uri().path().to_string();
         RequestContext {
             request,
             params: Params::default(),
             reader: UriReader::new(path),
             next: None,
-            stream,
+            stream: stream.into(),
+            body_reader: None,
+            buffered_body: OnceCell::new(),
+        }
+    }
+
+    /// Returns the request body.
+    ///
+    /// Bodies that didn't arrive together with the request head are read from
+    /// the TCP stream first, so guards and middleware see the whole body. If
+    /// reading the body fails, an empty body is returned and the error is
+    /// reported by [`buffer_body`](RequestContext::buffer_body).
+    ///
+    /// The body is empty if it was already consumed by a
+    /// [`BodyStream`](crate::extract::BodyStream).
+    pub fn body(&self) -> &Body<'static> {
+        match &self.body_reader {
+            Some(body_reader) => self
+                .buffered_body
+                .get_or_init(|| read_body(body_reader))
+                .as_ref()
+                .unwrap_or_else(|_| self.request.body()),
+            None => self.request.body(),
         }
     }
 
+    /// Reads the rest of the request body into memory, making it available
+    /// through the [`request`](RequestContext::request) too.
+    ///
+    /// Extractors such as [`Body`], [`String`] and [`Vec<u8>`] do this
+    /// automatically, while [`body`](RequestContext::body) reads the body
+    /// without reporting errors.
+    ///
+    /// Does nothing if the body was already read, including by a
+    /// [`BodyStream`](crate::extract::BodyStream).
+    pub fn buffer_body(&mut self) -> Result<(), FailedToBufferBody> {
+        if let Some(body_reader) = self.body_reader.take() {
+            let body = match self.buffered_body.take() {
+                Some(body) => body,
+                None => read_body(&body_reader),
+            };
+            match body {
+                Ok(body) => *self.request.body_mut() = body,
+                Err(err) if core::is_body_too_large(&err) => {
+                    return Err(LengthLimitError::from_err(err).into());
+                }
+                Err(err) => return Err(UnknownBodyError::from_err(err).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the reader of the part of the body that wasn't read yet.
+    ///
+    /// If the body was already read by [`body`](RequestContext::body), it's
+    /// moved into the request instead.
+    pub(crate) fn take_body_reader(&mut self) -> Option<Rc<RefCell<BodyReader>>> {
+        match self.buffered_body.get() {
+            Some(Ok(_)) => {
+                let _ = self.buffer_body();
+                None
+            }
+            _ => self.body_reader.take(),
+        }
+    }
+
+    /// Returns `true` if the request was received over TLS.
+    pub fn is_tls(&self) -> bool {
+        self.stream.is_tls()
+    }
+
     /// Call the next handler, returning the response.
     ///
     /// # Panics
@@ -58,6 +135,13 @@ impl RequestContext {
     }
 }
 
+/// Reads the rest of the body from the `body_reader`.
+fn read_body(body_reader: &RefCell<BodyReader>) -> io::Result<Body<'static>> {
+    let mut body = Vec::new();
+    body_reader.borrow_mut().read_to_end(&mut body)?;
+    Ok(Body::from_vec(body))
+}
+
 impl<'a> convert::AsRef<http::Request<Body<'a>>> for RequestContext {
     fn as_ref(&self) -> &http::Request<Body<'a>> {
         &self.request
@@ -1,10 +1,14 @@
 //! Types and traits for http responses.
 
+pub use connection::*;
 pub use into_response::*;
 pub use into_response_parts::*;
+pub use stream_body::*;
 
+mod connection;
 mod into_response;
 mod into_response_parts;
+mod stream_body;
 
 /// Type alias for [`http::Response`] whose body defaults to [`Vec<u8>`].
 pub type Response<T = Vec<u8>> = http::Response<T>;
@@ -0,0 +1,36 @@
+use std::convert::Infallible;
+
+use super::{IntoResponse, IntoResponseParts, Response, ResponseParts};
+
+/// Closes the connection after the response is sent.
+///
+/// By default connections are kept alive, unless the client asked otherwise.
+/// Adding `CloseConnection` to a response sets the `Connection: close` header
+/// and closes the connection once the response is written.
+///
+/// # Example
+///
+/// ```
+/// use submillisecond::response::CloseConnection;
+///
+/// fn goodbye() -> (CloseConnection, &'static str) {
+///     (CloseConnection, "Goodbye")
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CloseConnection;
+
+impl IntoResponseParts for CloseConnection {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
+
+impl IntoResponse for CloseConnection {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
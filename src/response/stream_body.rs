@@ -0,0 +1,83 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use http::{header, HeaderValue};
+
+use super::{IntoResponse, Response};
+
+/// Response body that is produced while it's being sent.
+///
+/// Each item of the iterator is written to the connection as soon as it's
+/// produced, using `Transfer-Encoding: chunked`. This allows sending large
+/// responses, such as exports or generated files, without holding them in
+/// memory. HTTP/1.0 clients don't support chunked encoding, so the connection
+/// is closed to mark the end of the body instead. Over HTTP/2, each item is
+/// sent in DATA frames.
+///
+/// The whole body needs to be sent within the configured
+/// [`request_timeout`](crate::ServerConfig::request_timeout).
+///
+/// # Example
+///
+/// ```
+/// use submillisecond::http::header;
+/// use submillisecond::response::{IntoResponse, StreamBody};
+///
+/// fn export() -> impl IntoResponse {
+///     let rows = (1..=1000).map(|id| format!("{id},user{id}\n"));
+///     ([(header::CONTENT_TYPE, "text/csv")], StreamBody::new(rows))
+/// }
+/// ```
+pub struct StreamBody(Mutex<Box<dyn Iterator<Item = Vec<u8>> + Send>>);
+
+impl StreamBody {
+    /// Creates a streaming body from an iterator of chunks.
+    pub fn new<I>(chunks: I) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: Send + 'static,
+        I::Item: Into<Vec<u8>>,
+    {
+        StreamBody(Mutex::new(Box::new(chunks.into_iter().map(Into::into))))
+    }
+
+    /// Writes all chunks to the stream, with or without chunked encoding.
+    pub(crate) fn write_to<W>(self, stream: &mut W, chunked: bool) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let chunks = self.0.into_inner().unwrap_or_else(|err| err.into_inner());
+        for chunk in chunks {
+            // An empty chunk would end the body
+            if chunk.is_empty() {
+                continue;
+            }
+            if chunked {
+                let mut buffer = format!("{:x}\r\n", chunk.len()).into_bytes();
+                buffer.extend(chunk);
+                buffer.extend(b"\r\n");
+                stream.write_all(&buffer)?;
+            } else {
+                stream.write_all(&chunk)?;
+            }
+        }
+        if chunked {
+            stream.write_all(b"0\r\n\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl IntoResponse for StreamBody {
+    fn into_response(self) -> Response {
+        let mut res = Response::builder()
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(mime::APPLICATION_OCTET_STREAM.as_ref()),
+            )
+            .body(Vec::new())
+            .unwrap();
+        res.extensions_mut().insert(self);
+        res
+    }
+}
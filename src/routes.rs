@@ -0,0 +1,97 @@
+//! Descriptions of the routes of a router.
+//!
+//! The [`router!`](crate::router) macro generates a static table of its
+//! routes, which can be printed or served to inspect the router.
+
+use std::fmt;
+
+/// Description of a route defined in the [`router!`](crate::router) macro.
+///
+/// # Example
+///
+/// ```
+/// use submillisecond::router;
+/// use submillisecond::routes::RouteInfo;
+///
+/// fn user_show() {}
+///
+/// router! {
+///     pub mod urls;
+///
+///     GET "/users/:id" => user_show
+/// }
+///
+/// fn main() {
+///     assert_eq!(
+///         urls::ROUTES,
+///         &[RouteInfo {
+///             method: Some("GET"),
+///             path: "/users/:id",
+///             handler: "user_show",
+///             guards: &[],
+///             middleware: &[],
+///         }]
+///     );
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RouteInfo {
+    /// The method of the route, or `None` if it handles any method.
+    pub method: Option<&'static str>,
+    /// The full path of the route, including the prefixes of sub-routers.
+    pub path: &'static str,
+    /// The handler of the route.
+    pub handler: &'static str,
+    /// The guards of the route, including the guards of sub-routers.
+    pub guards: &'static [&'static str],
+    /// The middleware of the route, including the middleware of routers.
+    pub middleware: &'static [&'static str],
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<7} {} => {}",
+            self.method.unwrap_or("*"),
+            self.path,
+            self.handler
+        )?;
+        if !self.guards.is_empty() {
+            write!(f, " if {}", self.guards.join(" && "))?;
+        }
+        if !self.middleware.is_empty() {
+            write!(f, " with [{}]", self.middleware.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RouteInfo;
+
+    #[test]
+    fn display_route() {
+        let route = RouteInfo {
+            method: Some("GET"),
+            path: "/admin/users/:id",
+            handler: "user_show",
+            guards: &["IsAdmin"],
+            middleware: &["logger", "auth"],
+        };
+        assert_eq!(
+            route.to_string(),
+            "GET     /admin/users/:id => user_show if IsAdmin with [logger, auth]"
+        );
+
+        let route = RouteInfo {
+            method: None,
+            path: "/static",
+            handler: "static_files",
+            guards: &[],
+            middleware: &[],
+        };
+        assert_eq!(route.to_string(), "*       /static => static_files");
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use headers::HeaderValue;
+use http::{header, StatusCode};
+use lunatic::net::{TcpListener, TlsListener};
+use lunatic::{Mailbox, MailboxError, MessageSignal, Process, ProcessConfig, Tag};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::failure::{ErrorHandler, Failure, FailureKind};
+use crate::supervisor::{
+    log_error, log_warn, request_supervisor, response_to_vec, SupervisorControl, SupervisorMessage,
+};
+use crate::{ConnectionLimitPolicy, Handler, ServerConfig, Stream};
+
+/// A handle for gracefully shutting down a server.
+///
+/// The handle refers to the process that created it, and shuts down the
+/// server running in that process. Once [`shutdown`](ShutdownHandle::shutdown)
+/// is called, the server stops accepting new connections. Requests that are
+/// already being handled can finish within
+/// [`ServerConfig::shutdown_timeout`](crate::ServerConfig::shutdown_timeout),
+/// after which the remaining connections are closed and
+/// [`Application::serve`](crate::Application::serve) returns.
+///
+/// # Example
+///
+/// ```
+/// use submillisecond::{router, Application, ShutdownHandle};
+///
+/// fn index() -> &'static str { "Welcome" }
+///
+/// let shutdown = ShutdownHandle::new();
+/// // Send the handle to another process, and call `shutdown.shutdown()` from there.
+/// Application::new(router! {
+///     GET "/" => index
+/// })
+/// .with_shutdown(shutdown)
+/// .serve("0.0.0.0:3000")
+/// ```
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ShutdownHandle {
+    server: Process<ServerMessage>,
+    tag: Tag,
+}
+
+impl ShutdownHandle {
+    /// Creates a shutdown handle for the server running in the current
+    /// process.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        // SAFETY:
+        //
+        // The mailbox is only used to get a reference to the current process,
+        // no messages are received with it.
+        let mailbox = unsafe { Mailbox::<ServerMessage>::new() };
+        ShutdownHandle {
+            server: mailbox.this(),
+            tag: Tag::new(),
+        }
+    }
+
+    /// Shuts down the server using this handle. If the server didn't start
+    /// yet, it shuts down right after starting.
+    pub fn shutdown(&self) {
+        self.server.tag_send(self.tag, ServerMessage::Shutdown);
+    }
+}
+
+/// Messages received by the process running the server.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum ServerMessage {
+    /// A new connection was accepted.
+    Accepted(Stream, SocketAddr),
+    /// The listener failed to accept connections.
+    AcceptFailed,
+    /// A connection was closed.
+    Closed(u64),
+    /// Stop accepting connections and finish the active ones.
+    Shutdown,
+}
+
+/// Used by a request supervisor to notify the server when the connection is
+/// closed.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ConnectionHandle {
+    server: Process<ServerMessage>,
+    tag: Tag,
+    id: u64,
+}
+
+impl ConnectionHandle {
+    /// Spawns a process linked to the calling request supervisor, which
+    /// notifies the server once the connection is closed.
+    pub(crate) fn watch(self) -> ConnectionWatcher {
+        ConnectionWatcher(Process::spawn_link(self, watch_connection))
+    }
+}
+
+/// Notifies the server when the connection of a request supervisor is closed.
+pub(crate) struct ConnectionWatcher(Process<()>);
+
+impl ConnectionWatcher {
+    /// Notifies the server that the connection was closed.
+    pub(crate) fn closed(self) {
+        self.0.send(());
+    }
+}
+
+/// Waits for the request supervisor to close the connection. If the
+/// supervisor fails instead, the server is notified all the same, so the
+/// connection doesn't count towards the limits forever.
+fn watch_connection(connection: ConnectionHandle, mailbox: Mailbox<()>) {
+    let mailbox = mailbox.catch_link_failure();
+    if let MessageSignal::Signal(_) = mailbox.receive() {
+        log_error("Request supervisor failed");
+    }
+    connection
+        .server
+        .tag_send(connection.tag, ServerMessage::Closed(connection.id));
+}
+
+/// A listener accepting TCP or TLS connections.
+pub(crate) trait Listener: Serialize + DeserializeOwned {
+    fn accept(&self) -> io::Result<(Stream, SocketAddr)>;
+}
+
+impl Listener for TcpListener {
+    fn accept(&self) -> io::Result<(Stream, SocketAddr)> {
+        let (stream, addr) = TcpListener::accept(self)?;
+        Ok((stream.into(), addr))
+    }
+}
+
+impl Listener for TlsListener {
+    fn accept(&self) -> io::Result<(Stream, SocketAddr)> {
+        let (stream, addr) = TlsListener::accept(self)?;
+        Ok((stream.into(), addr))
+    }
+}
+
+/// Accepts connections in a separate process, so the server can receive other
+/// messages in the meantime.
+///
+/// After each connection the acceptor waits for the server to resume it, which
+/// stops accepting connections while the server is at its connection limit.
+fn acceptor<L>((listener, server, tag): (L, Process<ServerMessage>, Tag), mailbox: Mailbox<()>)
+where
+    L: Listener,
+{
+    while let Ok((stream, addr)) = listener.accept() {
+        server.tag_send(tag, ServerMessage::Accepted(stream, addr));
+        mailbox.receive();
+    }
+    server.tag_send(tag, ServerMessage::AcceptFailed);
+}
+
+/// Answers a connection exceeding the connection limits with
+/// `503 Service Unavailable`, or the response of the error handler.
+fn reject_connection(
+    (mut stream, retry_after, error_handler, config): (
+        Stream,
+        Duration,
+        Option<ErrorHandler>,
+        ServerConfig,
+    ),
+    _: Mailbox<()>,
+) {
+    let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let failure = Failure::new(
+        FailureKind::ConnectionLimit,
+        StatusCode::SERVICE_UNAVAILABLE,
+    );
+    let mut response = match error_handler {
+        Some(error_handler) => (error_handler.get())(failure),
+        None => failure.default_response(),
+    };
+    let headers = response.headers_mut();
+    if !headers.contains_key(header::RETRY_AFTER) {
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+    let _ = stream.write_all(&response_to_vec(response, &config));
+}
+
+/// Keeps track of the active connections and the connection limits.
+struct Connections {
+    config: ServerConfig,
+    worker_config: Option<ProcessConfig>,
+    error_handler: Option<ErrorHandler>,
+    active: HashMap<u64, (Process<SupervisorMessage>, IpAddr)>,
+    per_ip: HashMap<IpAddr, usize>,
+    /// Connections held because their IP address reached its limit, with the
+    /// time they were accepted.
+    held: VecDeque<(Stream, SocketAddr, Instant)>,
+    next_id: u64,
+}
+
+impl Connections {
+    fn new(
+        config: ServerConfig,
+        worker_config: Option<ProcessConfig>,
+        error_handler: Option<ErrorHandler>,
+    ) -> Self {
+        Connections {
+            config,
+            worker_config,
+            error_handler,
+            active: HashMap::new(),
+            per_ip: HashMap::new(),
+            held: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Checks if the server can take another connection.
+    fn has_capacity(&self) -> bool {
+        match self.config.max_connections {
+            Some(max) => self.active.len() + self.held.len() < max,
+            None => true,
+        }
+    }
+
+    /// Checks if another connection from `ip` can be handled.
+    fn ip_has_capacity(&self, ip: &IpAddr) -> bool {
+        match self.config.max_connections_per_ip {
+            Some(max) => self.per_ip.get(ip).copied().unwrap_or_default() < max,
+            None => true,
+        }
+    }
+
+    /// Handles, holds or rejects a new connection.
+    fn accept<T, Arg, Ret>(
+        &mut self,
+        stream: Stream,
+        addr: SocketAddr,
+        handler: &T,
+        server: Process<ServerMessage>,
+        tag: Tag,
+    ) where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let policy = self.config.connection_limit_policy;
+        if self.has_capacity() && self.ip_has_capacity(&addr.ip()) {
+            self.start(stream, addr, handler, server, tag);
+        } else if let ConnectionLimitPolicy::Reject { retry_after } = policy {
+            log_warn(format!("Connection limit reached, rejecting {}", addr.ip()));
+            self.reject(stream, retry_after);
+        } else if self.held.len() >= self.config.max_held_connections {
+            log_warn(format!(
+                "Too many held connections, rejecting {}",
+                addr.ip()
+            ));
+            self.reject(stream, self.config.hold_timeout);
+        } else {
+            // With the `Hold` policy only connections exceeding the per IP limit
+            // get here, the acceptor is paused at the global limit.
+            self.held.push_back((stream, addr, Instant::now()));
+        }
+    }
+
+    /// Answers the connection with `503 Service Unavailable` in a separate
+    /// process.
+    fn reject(&self, stream: Stream, retry_after: Duration) {
+        Process::spawn(
+            (
+                stream,
+                retry_after,
+                self.error_handler.clone(),
+                self.config.clone(),
+            ),
+            reject_connection,
+        );
+    }
+
+    /// Returns the time until the oldest held connection expires.
+    fn hold_expiry(&self) -> Option<Duration> {
+        self.held.front().map(|(_, _, held_at)| {
+            (*held_at + self.config.hold_timeout).saturating_duration_since(Instant::now())
+        })
+    }
+
+    /// Rejects the connections that were held for too long.
+    fn expire_held(&mut self) {
+        while let Some((_, _, held_at)) = self.held.front() {
+            if held_at.elapsed() < self.config.hold_timeout {
+                break;
+            }
+            if let Some((stream, addr, _)) = self.held.pop_front() {
+                log_warn(format!("Held connection expired, rejecting {}", addr.ip()));
+                self.reject(stream, self.config.hold_timeout);
+            }
+        }
+    }
+
+    /// Spawns a request supervisor for the connection.
+    fn start<T, Arg, Ret>(
+        &mut self,
+        stream: Stream,
+        addr: SocketAddr,
+        handler: &T,
+        server: Process<ServerMessage>,
+        tag: Tag,
+    ) where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let connection = ConnectionHandle { server, tag, id };
+        // The supervisor isn't linked, so its failure can't take down the server. It
+        // notifies the server with a connection watcher instead.
+        let supervisor = Process::spawn(
+            (
+                stream,
+                handler.clone(),
+                self.config.clone(),
+                self.worker_config.clone(),
+                self.error_handler.clone(),
+                connection,
+            ),
+            request_supervisor::<T, Arg, Ret>,
+        );
+        self.active.insert(id, (supervisor, addr.ip()));
+        *self.per_ip.entry(addr.ip()).or_default() += 1;
+    }
+
+    /// Removes a closed connection, starting a held connection from the same
+    /// IP address.
+    fn closed<T, Arg, Ret>(
+        &mut self,
+        id: u64,
+        handler: &T,
+        server: Process<ServerMessage>,
+        tag: Tag,
+    ) where
+        T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+    {
+        let ip = match self.active.remove(&id) {
+            Some((_, ip)) => ip,
+            None => return,
+        };
+        if let Some(count) = self.per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_ip.remove(&ip);
+            }
+        }
+        let held = self.held.iter().position(|(_, addr, _)| addr.ip() == ip);
+        if let Some((stream, addr, _)) = held.and_then(|index| self.held.remove(index)) {
+            self.start(stream, addr, handler, server, tag);
+        }
+    }
+}
+
+/// Handles connections accepted by the listener, each in a request supervisor
+/// process, until the listener fails or the server is shut down.
+pub(crate) fn serve<L, T, Arg, Ret>(
+    listener: L,
+    handler: T,
+    config: ServerConfig,
+    worker_config: Option<ProcessConfig>,
+    error_handler: Option<ErrorHandler>,
+    shutdown: Option<ShutdownHandle>,
+) where
+    L: Listener,
+    T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
+{
+    // SAFETY:
+    //
+    // The mailbox belongs to the process calling `serve`, which could be
+    // receiving messages of other types. All messages for the server are sent
+    // with a unique tag, and only messages with this tag are received here, so
+    // other messages are left untouched.
+    let mailbox = unsafe { Mailbox::<ServerMessage>::new() };
+    let this = mailbox.this();
+    let tag = Tag::new();
+    // The shutdown handle sends its message with its own tag, so a shutdown
+    // requested before the server started is received too.
+    let tags = match shutdown {
+        Some(shutdown) => {
+            assert!(
+                shutdown.server.id() == this.id(),
+                "a `ShutdownHandle` can only shut down a server running in the process that \
+                 created it"
+            );
+            vec![tag, shutdown.tag]
+        }
+        None => vec![tag],
+    };
+    let acceptor = Process::spawn_link((listener, this, tag), acceptor::<L>);
+
+    let mut connections = Connections::new(config.clone(), worker_config, error_handler);
+    let mut acceptor_paused = false;
+    loop {
+        // Held connections expire while waiting for other connections to close
+        let message = match connections.hold_expiry() {
+            Some(expiry) => mailbox.tag_receive_timeout(&tags, expiry),
+            None => Ok(mailbox.tag_receive(&tags)),
+        };
+        match message {
+            Ok(ServerMessage::Accepted(stream, addr)) => {
+                connections.accept(stream, addr, &handler, this, tag);
+                acceptor_paused = true;
+            }
+            Ok(ServerMessage::Closed(id)) => {
+                connections.closed(id, &handler, this, tag);
+            }
+            Ok(ServerMessage::AcceptFailed) => return,
+            Ok(ServerMessage::Shutdown) => break,
+            Err(MailboxError::TimedOut) => connections.expire_held(),
+            Err(MailboxError::DeserializationFailed(err)) => {
+                panic!("failed to deserialize server message: {err}");
+            }
+        }
+        // Connections over the limit are rejected right away, so the acceptor is
+        // only paused when holding them.
+        let hold = config.connection_limit_policy == ConnectionLimitPolicy::Hold;
+        if acceptor_paused && (connections.has_capacity() || !hold) {
+            acceptor.send(());
+            acceptor_paused = false;
+        }
+    }
+
+    // Stop accepting connections, and let the active ones finish their current
+    // request.
+    acceptor.unlink();
+    acceptor.kill();
+    let mut active = connections.active;
+    for (supervisor, _) in active.values() {
+        supervisor.send(SupervisorMessage::Control(SupervisorControl::Shutdown));
+    }
+    let deadline = Instant::now() + config.shutdown_timeout;
+    while !active.is_empty() {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match mailbox.tag_receive_timeout(&[tag], timeout) {
+            Ok(ServerMessage::Closed(id)) => {
+                active.remove(&id);
+            }
+            // Connections accepted right before the shutdown are dropped
+            Ok(_) => {}
+            Err(MailboxError::TimedOut) => break,
+            Err(MailboxError::DeserializationFailed(err)) => {
+                panic!("failed to deserialize server message: {err}");
+            }
+        }
+    }
+    // Connections that didn't finish in time are closed
+    for (supervisor, _) in active.into_values() {
+        supervisor.kill();
+    }
+}
@@ -0,0 +1,290 @@
+//! Server-sent events.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use http::header::{CACHE_CONTROL, CONNECTION, CONTENT_TYPE};
+use http::{StatusCode, Version};
+use lunatic::function::FuncRef;
+use lunatic::{Mailbox, MailboxError, Process};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::extract::FromRequest;
+use crate::response::{IntoResponse, Response};
+use crate::supervisor::{Connection, SupervisorResponse};
+use crate::{RequestContext, Stream};
+
+/// Server-sent events extractor which turns the response into an event
+/// stream.
+///
+/// # Example
+///
+/// ```
+/// use submillisecond::router;
+/// use submillisecond::sse::{Event, Sse, SseConnection, SseResponse};
+///
+/// fn updates(sse: Sse) -> SseResponse {
+///     sse.on_upgrade((), |mut conn: SseConnection<String>, ()| {
+///         // Other processes can send messages to `conn.this()`
+///         while let Some(update) = conn.receive() {
+///             if conn.send(&Event::default().event("update").data(update)).is_err() {
+///                 break;
+///             }
+///         }
+///     })
+/// }
+///
+/// router! {
+///     GET "/updates" => updates
+/// }
+/// ```
+pub struct Sse {
+    stream: Stream,
+    keep_alive: Duration,
+}
+
+impl Sse {
+    /// Sets the interval for sending keep-alive comments while no events are
+    /// sent. The default value is 15 seconds.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    /// Spawns a process with the event stream provided to the callback.
+    pub fn on_upgrade<C, M>(self, captures: C, callback: fn(SseConnection<M>, C)) -> SseResponse
+    where
+        C: Serialize + for<'de> Deserialize<'de>,
+        M: Serialize + DeserializeOwned,
+    {
+        let stream = self.stream.clone();
+        let callback = FuncRef::new(callback);
+        let process = Process::spawn_link(
+            (stream, callback, captures, self.keep_alive),
+            |(stream, callback, captures, keep_alive), mailbox: Mailbox<SupervisorResponse>| {
+                if let Ok(SupervisorResponse::ResponseSent) =
+                    mailbox.receive_timeout(Duration::from_secs(5))
+                {
+                    // SAFETY: The supervisor only sends a single message, after it the
+                    // mailbox is used exclusively for messages of type `M`.
+                    let mailbox = unsafe { Mailbox::<M>::new() };
+                    let conn = SseConnection {
+                        stream,
+                        mailbox,
+                        keep_alive,
+                    };
+                    callback(conn, captures);
+                }
+            },
+        );
+
+        SseResponse { process }
+    }
+}
+
+impl FromRequest for Sse {
+    type Rejection = SseRejection;
+
+    fn from_request(req: &mut RequestContext) -> Result<Self, Self::Rejection> {
+        // HTTP/2 connections are shared by multiple streams and can't be taken over
+        if req.version() >= Version::HTTP_2 {
+            return Err(SseRejection::Http2NotSupported);
+        }
+        Ok(Sse {
+            stream: req.stream.clone(),
+            keep_alive: Duration::from_secs(15),
+        })
+    }
+}
+
+/// Server-sent events rejection.
+pub enum SseRejection {
+    /// Event streams over HTTP/2 are not supported.
+    Http2NotSupported,
+}
+
+impl IntoResponse for SseRejection {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            SseRejection::Http2NotSupported => (
+                StatusCode::NOT_IMPLEMENTED,
+                "server-sent events over http/2 are not supported",
+            ),
+        };
+
+        (status, body).into_response()
+    }
+}
+
+/// An event stream response.
+pub struct SseResponse {
+    process: Process<SupervisorResponse>,
+}
+
+impl IntoResponse for SseResponse {
+    fn into_response(self) -> Response {
+        // The event stream ends when the connection is closed.
+        Response::builder()
+            .header(CONTENT_TYPE, mime::TEXT_EVENT_STREAM.as_ref())
+            .header(CACHE_CONTROL, "no-cache")
+            .header(CONNECTION, "close")
+            .extension(Connection::Upgrade(self.process))
+            .body(Vec::new())
+            .unwrap()
+    }
+}
+
+/// An event stream connection.
+///
+/// The process running the stream receives messages of type `M`, which
+/// other processes can send to [`SseConnection::this`].
+pub struct SseConnection<M = ()> {
+    stream: Stream,
+    mailbox: Mailbox<M>,
+    keep_alive: Duration,
+}
+
+impl<M> SseConnection<M>
+where
+    M: Serialize + DeserializeOwned,
+{
+    /// Sends an event to the client.
+    ///
+    /// Fails if the client disconnected.
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        self.stream.write_all(event.to_string().as_bytes())
+    }
+
+    /// Waits for the next message sent to this process.
+    ///
+    /// While no messages arrive, keep-alive comments are sent to the client.
+    /// Returns `None` once the client disconnected.
+    pub fn receive(&mut self) -> Option<M> {
+        loop {
+            match self.mailbox.receive_timeout(self.keep_alive) {
+                Ok(message) => return Some(message),
+                Err(MailboxError::TimedOut) => {
+                    if self.stream.write_all(b":\n\n").is_err() || self.is_closed() {
+                        return None;
+                    }
+                }
+                Err(MailboxError::DeserializationFailed(err)) => {
+                    panic!("failed to deserialize event stream message: {err}");
+                }
+            }
+        }
+    }
+
+    /// Returns the process running the event stream.
+    pub fn this(&self) -> Process<M> {
+        self.mailbox.this()
+    }
+
+    /// Checks if the client closed the connection.
+    ///
+    /// Clients don't send any data on an event stream, so reaching the end of
+    /// the stream means the connection was closed.
+    pub fn is_closed(&mut self) -> bool {
+        let _ = self.stream.set_read_timeout(Some(Duration::from_millis(1)));
+        let result = self.stream.read(&mut [0_u8; 64]);
+        let _ = self.stream.set_read_timeout(None);
+        match result {
+            Ok(n) => n == 0,
+            Err(err) => !matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ),
+        }
+    }
+}
+
+/// A server-sent event.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use submillisecond::sse::Event;
+///
+/// let event = Event::default()
+///     .id("42")
+///     .event("message")
+///     .data("first line\nsecond line")
+///     .retry(Duration::from_secs(5));
+/// assert_eq!(
+///     event.to_string(),
+///     "id: 42\nevent: message\nretry: 5000\ndata: first line\ndata: second line\n\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Sets the event id, which the client sends back in the `Last-Event-ID`
+    /// header when reconnecting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id contains newlines or carriage returns.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(single_line("id", id.into()));
+        self
+    }
+
+    /// Sets the event type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the event type contains newlines or carriage returns.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(single_line("event", event.into()));
+        self
+    }
+
+    /// Sets the event data. Multiple lines are sent as multiple `data` fields.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Sets the time the client waits before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {id}")?;
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {event}")?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        if let Some(data) = &self.data {
+            for line in data.split('\n') {
+                writeln!(f, "data: {}", line.strip_suffix('\r').unwrap_or(line))?;
+            }
+        }
+        f.write_char('\n')
+    }
+}
+
+fn single_line(field: &str, value: String) -> String {
+    if value.contains(['\n', '\r']) {
+        panic!("sse {field} cannot contain newlines or carriage returns");
+    }
+    value
+}
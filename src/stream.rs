@@ -0,0 +1,71 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use lunatic::net::{TcpStream, TlsStream};
+use serde::{Deserialize, Serialize};
+
+/// A connection to a client, either over plain TCP or TLS.
+///
+/// Requests served with [`Application::serve`](crate::Application::serve)
+/// use a [`TcpStream`], while requests served with
+/// [`Application::serve_tls`](crate::Application::serve_tls) use a
+/// [`TlsStream`].
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Stream {
+    /// A plain TCP connection.
+    Tcp(TcpStream),
+    /// A TLS connection.
+    Tls(TlsStream),
+}
+
+impl Stream {
+    /// Returns `true` if the connection is encrypted with TLS.
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Stream::Tls(_))
+    }
+
+    /// Sets the read timeout, or removes it with `None`.
+    pub fn set_read_timeout(&mut self, duration: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(duration),
+            Stream::Tls(stream) => stream.set_read_timeout(duration),
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+impl From<TlsStream> for Stream {
+    fn from(stream: TlsStream) -> Self {
+        Stream::Tls(stream)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
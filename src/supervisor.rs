@@ -1,36 +1,115 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::io::Write;
-use std::time::Duration;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use headers::HeaderValue;
-use http::{header, Request, StatusCode, Version};
-use lunatic::net::TcpStream;
-use lunatic::{Mailbox, MailboxError, MessageSignal, Process};
+use headers::{Header, HeaderValue};
+use http::{header, HeaderMap, Method, Request, StatusCode, Version};
+use lunatic::{Mailbox, MailboxError, MessageSignal, Process, ProcessConfig, Tag};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 use crate::core::{Body, ParseRequestError};
-use crate::response::{IntoResponse, Response};
-use crate::{core, Handler, RequestContext};
+use crate::failure::{ErrorHandler, Failure, FailureKind, RequestInfo};
+use crate::response::{CloseConnection, IntoResponse, Response, StreamBody};
+use crate::server::ConnectionHandle;
+use crate::{core, Handler, RequestContext, ServerConfig, Stream};
 
 #[derive(Serialize, Deserialize)]
 #[serde(bound(serialize = "T: Serialize", deserialize = "T: DeserializeOwned"))]
 pub(crate) struct WorkerRequest<T> {
-    supervisor: Process<WorkerResponse>,
-    stream: TcpStream,
+    supervisor: SupervisorHandle,
+    stream: Stream,
     handler: T,
+    config: ServerConfig,
+    keep_alive: bool,
     #[serde(with = "serde_bytes")]
     request_buffer: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) enum WorkerResponse {
+    /// The request was read and is being handled.
+    RequestParsed(RequestInfo),
     /// Response contains the HTTP response and sometimes data from a pipelined
     /// request.
     Response(#[serde(with = "serde_bytes")] Vec<u8>, Connection),
+    /// The worker started writing a streaming response to the TCP stream.
+    StreamingResponse,
+    /// The worker finished writing a streaming response to the TCP stream.
+    StreamedResponse(Connection),
+    /// The worker panicked, sent by its panic hook.
+    Panicked(WorkerPanic),
+    /// The response to a failure, rendered by the error handler.
+    FailureResponse(#[serde(with = "serde_bytes")] Vec<u8>),
     /// The TCP connection was closed before any data arrived.
     TcpClosed,
+    /// The request could not be parsed.
+    InvalidRequest(ParseRequestError),
     /// Failed to process request.
     Failure(String),
+    /// The connection switches to HTTP/2.
+    #[cfg(feature = "http2")]
+    Http2(crate::http2::Http2Start),
+    /// The HTTP/2 connection was closed.
+    #[cfg(feature = "http2")]
+    Http2Closed,
+}
+
+/// Messages received by a request supervisor.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum SupervisorMessage {
+    /// Sent by the processes handling the connection.
+    Worker(WorkerResponse),
+    /// Sent by the server.
+    Control(SupervisorControl),
+}
+
+/// Messages from the server controlling a request supervisor.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum SupervisorControl {
+    /// The server is shutting down. The connection is closed after the current
+    /// response.
+    Shutdown,
+}
+
+/// Used by the processes handling a connection to report to its supervisor.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct SupervisorHandle(Process<SupervisorMessage>);
+
+impl SupervisorHandle {
+    pub(crate) fn send(&self, response: WorkerResponse) {
+        self.0.send(SupervisorMessage::Worker(response));
+    }
+}
+
+/// Details of a worker panic, sent by the panic hook of the worker.
+///
+/// Workers report panics before failing, so a worker failing without a report
+/// was stopped for another reason, possibly its resource limits.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WorkerPanic {
+    /// The panic message.
+    pub(crate) message: String,
+    /// The source location of the panic.
+    pub(crate) location: Option<String>,
+    /// The request line of the request being handled.
+    pub(crate) request_line: Option<String>,
+}
+
+impl fmt::Display for WorkerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}'", self.message)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {location}")?;
+        }
+        if let Some(request_line) = &self.request_line {
+            write!(f, " handling `{request_line}`")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -46,6 +125,8 @@ pub(crate) enum Connection {
     /// Keep the connection alive, using the `Vec<u8>` as the start of the next
     /// request.
     KeepAlive(#[serde(with = "serde_bytes")] Vec<u8>),
+    /// Close the connection after the response is sent.
+    Close,
     /// Upgrade the connection and close. It is expected that a process was
     /// spawned by a handler to handle the upgraded connection with the cloned
     /// TcpStream.
@@ -53,41 +134,169 @@ pub(crate) enum Connection {
 }
 
 pub(crate) fn request_supervisor<T, Arg, Ret>(
-    (mut stream, handler): (TcpStream, T),
-    mailbox: Mailbox<WorkerResponse>,
+    (mut stream, handler, config, worker_config, error_handler, connection): (
+        Stream,
+        T,
+        ServerConfig,
+        Option<ProcessConfig>,
+        Option<ErrorHandler>,
+        ConnectionHandle,
+    ),
+    mailbox: Mailbox<SupervisorMessage>,
 ) where
     T: Handler<Arg, Ret> + Clone + Serialize + DeserializeOwned,
 {
-    let supervisor = mailbox.this();
+    let supervisor = SupervisorHandle(mailbox.this());
     let mut request_buffer: Vec<u8> = Vec::new();
+    // The first request on a connection is not kept alive.
+    let mut keep_alive = false;
+    let mut shutting_down = false;
 
     // Failure in linked worker processes should not kill the supervisor
     let mailbox = mailbox.catch_link_failure();
+    // Notifies the server once the connection is closed, even if the supervisor
+    // fails
+    let watcher = connection.watch();
+
+    // Responds to failures, using the error handler if there is one. The error
+    // handler runs in a separate process, so it can't take down the supervisor.
+    let write_failure = |stream: &mut Stream, failure: Failure| {
+        let response = error_handler.as_ref().and_then(|error_handler| {
+            let tag = Tag::new();
+            let renderer = Process::spawn_link_tag(
+                (
+                    failure.clone(),
+                    error_handler.clone(),
+                    config.clone(),
+                    supervisor,
+                ),
+                tag,
+                render_failure,
+            );
+            loop {
+                match mailbox.receive_timeout(config.request_timeout) {
+                    Ok(MessageSignal::Message(SupervisorMessage::Worker(
+                        WorkerResponse::FailureResponse(data),
+                    ))) => {
+                        break Some(data);
+                    }
+                    Ok(MessageSignal::Signal(signal)) if signal == tag => {
+                        log_error("Error handler panicked");
+                        break None;
+                    }
+                    // Messages and signals of the failed worker are ignored
+                    Ok(_) => {}
+                    Err(_) => {
+                        renderer.kill();
+                        log_error("Error handler failed to render the response");
+                        break None;
+                    }
+                }
+            }
+        });
+        let response =
+            response.unwrap_or_else(|| response_to_vec(failure.default_response(), &config));
+        if let Err(err) = stream.write_all(&response) {
+            log_error(format!("Failed to send response: {err:?}"));
+        }
+    };
 
     // keep-alive loop
     'keepalive: loop {
         // Spawn worker process
-        let worker = Process::spawn_link(
-            WorkerRequest {
-                supervisor,
-                stream: stream.clone(),
-                handler: handler.clone(),
-                request_buffer,
-            },
-            request_woker::<T, Arg, Ret>,
-        );
+        let worker_request = WorkerRequest {
+            supervisor,
+            stream: stream.clone(),
+            handler: handler.clone(),
+            config: config.clone(),
+            keep_alive,
+            request_buffer,
+        };
+        let worker = match &worker_config {
+            Some(worker_config) => Process::spawn_link_config(
+                worker_config,
+                worker_request,
+                request_woker::<T, Arg, Ret>,
+            ),
+            None => Process::spawn_link(worker_request, request_woker::<T, Arg, Ret>),
+        };
 
-        // Each request has a default 5 minute timeout.
-        match mailbox.receive_timeout(Duration::from_secs(5 * 60)) {
+        // The worker closes idle connections and times out slow requests by itself,
+        // the supervisor only limits the time spent reading the request and, once
+        // it's parsed, handling it and streaming the response.
+        let mut handling = false;
+        let mut streaming = false;
+        let mut panicked = None;
+        let mut request_info = None;
+        let mut timeout = read_timeout(&config, keep_alive);
+        let message = loop {
+            let message = match mailbox.receive_timeout(timeout) {
+                Ok(MessageSignal::Message(SupervisorMessage::Control(
+                    SupervisorControl::Shutdown,
+                ))) => {
+                    shutting_down = true;
+                    // Waiting for the next request is cut short
+                    if !handling {
+                        worker.kill();
+                        break 'keepalive;
+                    }
+                    // A streaming worker writes the response head itself, so it's told to
+                    // close the connection
+                    worker.send(());
+                    continue;
+                }
+                Ok(MessageSignal::Message(SupervisorMessage::Worker(response))) => {
+                    Ok(MessageSignal::Message(response))
+                }
+                Ok(MessageSignal::Signal(tag)) => Ok(MessageSignal::Signal(tag)),
+                Err(err) => Err(err),
+            };
+            match message {
+                Ok(MessageSignal::Message(WorkerResponse::RequestParsed(info))) => {
+                    request_info = Some(info);
+                    handling = true;
+                    timeout = config.request_timeout;
+                }
+                Ok(MessageSignal::Message(WorkerResponse::StreamingResponse)) => {
+                    streaming = true;
+                    timeout = config.request_timeout;
+                }
+                Ok(MessageSignal::Message(WorkerResponse::Panicked(panic))) => {
+                    panicked = Some(panic);
+                }
+                message => break message,
+            }
+        };
+        match message {
             Ok(MessageSignal::Message(msg)) => match msg {
+                WorkerResponse::RequestParsed(_)
+                | WorkerResponse::StreamingResponse
+                | WorkerResponse::Panicked(_)
+                | WorkerResponse::FailureResponse(_) => unreachable!(),
+                #[cfg(feature = "http2")]
+                WorkerResponse::Http2Closed => unreachable!(),
                 WorkerResponse::Response(ref data, Connection::KeepAlive(next)) => {
-                    let result = stream.write_all(data);
+                    // The connection is closed after the response once the server is
+                    // shutting down, so the client doesn't reuse it
+                    let result = if shutting_down {
+                        stream.write_all(&close_connection(data))
+                    } else {
+                        stream.write_all(data)
+                    };
                     if let Err(err) = result {
                         log_error(format!("Failed to send response: {err:?}"));
                         break 'keepalive;
                     }
 
                     request_buffer = next;
+                    keep_alive = true;
+                }
+                WorkerResponse::Response(ref data, Connection::Close) => {
+                    let result = stream.write_all(data);
+                    if let Err(err) = result {
+                        log_error(format!("Failed to send response: {err:?}"));
+                    }
+                    break 'keepalive;
                 }
                 WorkerResponse::Response(ref data, Connection::Upgrade(process)) => {
                     match stream.write_all(data) {
@@ -102,62 +311,158 @@ pub(crate) fn request_supervisor<T, Arg, Ret>(
 
                     break 'keepalive;
                 }
+                WorkerResponse::StreamedResponse(Connection::KeepAlive(next)) => {
+                    request_buffer = next;
+                    keep_alive = true;
+                }
+                WorkerResponse::StreamedResponse(Connection::Close) => {
+                    break 'keepalive;
+                }
+                WorkerResponse::StreamedResponse(Connection::Upgrade(process)) => {
+                    process.send(SupervisorResponse::ResponseSent);
+                    break 'keepalive;
+                }
                 WorkerResponse::Failure(ref err) => {
                     log_error(err);
-                    let response: Response =
-                        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
-                            .into_response();
-                    let response = response_to_vec(response);
-                    let result = stream.write_all(&response);
-                    if let Err(err) = result {
-                        log_error(format!("Failed to send response: {err:?}"));
+                    let failure =
+                        Failure::new(FailureKind::Internal, StatusCode::INTERNAL_SERVER_ERROR)
+                            .with_request(request_info.as_ref())
+                            .with_message(err);
+                    write_failure(&mut stream, failure);
+                    break 'keepalive;
+                }
+                #[cfg(feature = "http2")]
+                WorkerResponse::Http2(start) => {
+                    // The connection is taken over by a process handling all HTTP/2 streams.
+                    // The supervisor stays alive until it's closed, so the connection still
+                    // counts towards the connection limits and is part of the shutdown.
+                    let tag = Tag::new();
+                    let http2 = Process::spawn_link_tag(
+                        (
+                            stream.clone(),
+                            handler.clone(),
+                            config.clone(),
+                            worker_config.clone(),
+                            error_handler.clone(),
+                            supervisor,
+                            start,
+                        ),
+                        tag,
+                        crate::http2::connection::<T, Arg, Ret>,
+                    );
+                    if shutting_down {
+                        http2.send(crate::http2::ConnectionMessage::Shutdown);
+                    }
+                    loop {
+                        match mailbox.receive() {
+                            MessageSignal::Message(SupervisorMessage::Worker(
+                                WorkerResponse::Http2Closed,
+                            )) => break,
+                            MessageSignal::Message(SupervisorMessage::Control(
+                                SupervisorControl::Shutdown,
+                            )) => {
+                                http2.send(crate::http2::ConnectionMessage::Shutdown);
+                            }
+                            MessageSignal::Signal(signal) if signal == tag => {
+                                log_error("HTTP/2 connection process failed");
+                                break;
+                            }
+                            // Messages and signals of the finished worker are ignored
+                            _ => {}
+                        }
                     }
                     break 'keepalive;
                 }
                 WorkerResponse::TcpClosed => {
-                    // If the `TcpStream` was closed without sending any data,
-                    // request is ignored.
+                    // If the `TcpStream` was closed or stayed idle without sending any
+                    // data, request is ignored.
+                    break 'keepalive;
+                }
+                WorkerResponse::InvalidRequest(ref err) => {
+                    log_warn(format!("Invalid request: {err}"));
+                    // If the client misbehaves the connection is closed, as it's not
+                    // possible to know where the next request would start.
+                    if let Some(status) = parse_error_status(err) {
+                        let kind = match err {
+                            ParseRequestError::ReadTimeout => FailureKind::ReadTimeout,
+                            _ => FailureKind::InvalidRequest,
+                        };
+                        let failure = Failure::new(kind, status).with_message(err);
+                        write_failure(&mut stream, failure);
+                    }
                     break 'keepalive;
                 }
             },
-            Ok(MessageSignal::Signal(_)) => {
-                log_error("Worker process panicked");
-                let response: Response =
-                    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response();
-                let response = response_to_vec(response);
-                let result = stream.write_all(&response);
-                if let Err(err) = result {
-                    log_error(format!("Failed to send response: {err:?}"));
+            Ok(MessageSignal::Signal(_)) if streaming => {
+                // Part of the response was already sent, it can only be cut off.
+                match &panicked {
+                    Some(panic) => log_error(format!(
+                        "Worker process panicked while streaming the response: {panic}"
+                    )),
+                    None if worker_config.is_some() => log_error(
+                        "Worker process failed without panicking while streaming the response, \
+                         possibly resource limits",
+                    ),
+                    None => log_error(
+                        "Worker process failed without panicking while streaming the response",
+                    ),
                 }
                 break 'keepalive;
             }
+            Ok(MessageSignal::Signal(_)) => {
+                let failure = worker_failure(panicked.as_ref(), worker_config.is_some());
+                write_failure(&mut stream, failure.with_request(request_info.as_ref()));
+                break 'keepalive;
+            }
             Err(MailboxError::TimedOut) => {
                 // Kill worker
                 worker.kill();
-                log_error("Request timed out");
-                let response: Response =
-                    (StatusCode::REQUEST_TIMEOUT, "Request timed out").into_response();
-                let response = response_to_vec(response);
-                let result = stream.write_all(&response);
-                if let Err(err) = result {
-                    log_error(format!("Failed to send response: {err:?}"));
+                if streaming {
+                    // Part of the response was already sent, it can only be cut off.
+                    log_error("Streaming the response timed out");
+                    break 'keepalive;
                 }
+                let kind = if handling {
+                    log_error("Request timed out");
+                    FailureKind::RequestTimeout
+                } else {
+                    log_warn("Timed out reading request");
+                    FailureKind::ReadTimeout
+                };
+                let failure = Failure::new(kind, StatusCode::REQUEST_TIMEOUT)
+                    .with_request(request_info.as_ref());
+                write_failure(&mut stream, failure);
                 break 'keepalive;
             }
             Err(MailboxError::DeserializationFailed(err)) => {
                 panic!("failed to deserialize worker message: {err}");
             }
         }
+
+        if shutting_down {
+            break 'keepalive;
+        }
     }
+
+    watcher.closed();
 }
 
 /// Request workers are processes that do all the request parsing and handling.
 /// At the end they return a buffer to the supervisor to send the response back
 /// to the client.
-fn request_woker<T, Arg, Ret>(mut worker_request: WorkerRequest<T>, _: Mailbox<()>)
+///
+/// The supervisor sends a message to the worker once the server is shutting
+/// down.
+fn request_woker<T, Arg, Ret>(mut worker_request: WorkerRequest<T>, mailbox: Mailbox<()>)
 where
     T: Handler<Arg, Ret> + Serialize + DeserializeOwned,
 {
+    let supervisor = worker_request.supervisor;
+    let request_line: Arc<Mutex<Option<String>>> = Arc::default();
+    set_panic_hook(request_line.clone(), move |panic| {
+        supervisor.send(WorkerResponse::Panicked(panic));
+    });
+
     let handler = worker_request.handler;
     let mut requests_buffer = worker_request.request_buffer;
     // SAFETY:
@@ -175,9 +480,16 @@ where
     // and `requests_buffer` will only be dropped right before the process finishes.
     let requests_buffer = unsafe { std::mem::transmute(&mut requests_buffer) };
 
-    let pipelined_request = core::parse_requests(requests_buffer, &mut worker_request.stream);
+    let pipelined_request = core::parse_requests(
+        requests_buffer,
+        &mut worker_request.stream,
+        &worker_request.config,
+        worker_request.keep_alive,
+    );
+    // Handlers and upgraded connections are not limited by the read timeouts.
+    let _ = worker_request.stream.set_read_timeout(None);
 
-    let (request, next) = pipelined_request.pipeline();
+    let (request, next, body_reader) = pipelined_request.pipeline();
     // Check if first request is valid
     let request = match request {
         Ok(request) => request,
@@ -186,42 +498,338 @@ where
             worker_request.supervisor.send(WorkerResponse::TcpClosed);
             return; // Abort request handling
         }
+        #[cfg(feature = "http2")]
+        Err(ParseRequestError::Http2Preface) => {
+            let start = crate::http2::Http2Start::from_preface(next);
+            worker_request.supervisor.send(WorkerResponse::Http2(start));
+            return;
+        }
         Err(error) => {
             worker_request
                 .supervisor
-                .send(WorkerResponse::Failure(format!(
-                    "Request parsing failed: {error:?}"
-                )));
+                .send(WorkerResponse::InvalidRequest(error));
             return; // Abort request handling
         }
     };
 
+    #[cfg(feature = "http2")]
+    if let Some(settings) = crate::http2::h2c_upgrade_settings(&request) {
+        // The upgraded request becomes the first HTTP/2 stream, so its body is read
+        // before switching protocols.
+        let mut body = request.body().as_slice().to_vec();
+        let next = match body_reader {
+            Some(mut body_reader) => {
+                match std::io::Read::read_to_end(&mut body_reader, &mut body) {
+                    Ok(_) => body_reader.finish().unwrap_or_default(),
+                    Err(err) => {
+                        let error = if core::is_body_too_large(&err) {
+                            ParseRequestError::RequestTooLarge
+                        } else {
+                            ParseRequestError::TcpStreamClosed
+                        };
+                        worker_request
+                            .supervisor
+                            .send(WorkerResponse::InvalidRequest(error));
+                        return;
+                    }
+                }
+            }
+            None => next,
+        };
+        let start = crate::http2::Http2Start::from_upgrade(&request, body, settings, next);
+        worker_request.supervisor.send(WorkerResponse::Http2(start));
+        return;
+    }
+
+    worker_request
+        .supervisor
+        .send(WorkerResponse::RequestParsed(RequestInfo::new(&request)));
+    if let Ok(mut request_line) = request_line.lock() {
+        *request_line = Some(format!(
+            "{} {} {:?}",
+            request.method(),
+            request.uri(),
+            request.version()
+        ));
+    }
     log_request(&request);
 
-    let response = Handler::handle(
-        &handler,
-        RequestContext::new(request, worker_request.stream),
-    )
-    .into_response();
-    let connection = response
-        .extensions()
-        .get::<Connection>()
-        .cloned()
-        .unwrap_or(Connection::KeepAlive(next));
-    let response = response_to_vec(response);
+    let version = request.version();
+    let is_head = request.method() == Method::HEAD;
+    let keep_alive = is_keep_alive(version, request.headers());
+    let body_reader = body_reader.map(|body_reader| Rc::new(RefCell::new(body_reader)));
+    let mut stream = worker_request.stream.clone();
+    let mut request = RequestContext::new(request, worker_request.stream);
+    request.body_reader = body_reader.clone();
+    let mut response = Handler::handle(&handler, request).into_response();
+    // If the body wasn't fully read by the handler, the next request starts after
+    // the rest of it.
+    let next = match body_reader {
+        Some(body_reader) => body_reader.borrow_mut().finish(),
+        None => Some(next),
+    };
+    let mut stream_body = response.extensions_mut().remove::<StreamBody>();
+    // Responses to HEAD requests have the headers of the response to the same GET
+    // request, without the body.
+    if is_head {
+        // The length of a streaming body is unknown
+        let streaming = stream_body.take().is_some();
+        if !streaming
+            && (!response.body().is_empty()
+                || !response.headers().contains_key(header::CONTENT_LENGTH))
+        {
+            let content_length = response.body().len();
+            response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+        }
+        response.body_mut().clear();
+    }
+    // HTTP/1.0 clients don't support chunked encoding, the end of a streaming body
+    // is marked by closing the connection.
+    let chunked = stream_body.is_some() && version != Version::HTTP_10;
+    let close = response.extensions().get::<CloseConnection>().is_some()
+        || has_connection_option(response.headers(), "close")
+        || (stream_body.is_some() && !chunked);
+    let connection = match response.extensions().get::<Connection>().cloned() {
+        Some(connection) => connection,
+        None => match next {
+            Some(next) if keep_alive && !close => Connection::KeepAlive(next),
+            _ => Connection::Close,
+        },
+    };
+    // Let the client know if the connection stays open
+    match connection {
+        Connection::KeepAlive(_) if version == Version::HTTP_10 => {
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+        }
+        Connection::Close => {
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+        }
+        _ => {}
+    }
+    *response.version_mut() = version;
+
+    let stream_body = match stream_body {
+        Some(stream_body) => stream_body,
+        None => {
+            // Upgraded connections are taken over by another process, so the length
+            // of the response body is unknown.
+            let response = match connection {
+                Connection::Upgrade(_) => {
+                    response_head_to_vec(&mut response, &worker_request.config)
+                }
+                _ if is_head => response_head_to_vec(&mut response, &worker_request.config),
+                _ => response_to_vec(response, &worker_request.config),
+            };
+            worker_request
+                .supervisor
+                .send(WorkerResponse::Response(response, connection));
+            return;
+        }
+    };
+    // The connection is closed once the server is shutting down
+    let connection = match connection {
+        Connection::KeepAlive(_) if mailbox.receive_timeout(Duration::ZERO).is_ok() => {
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+            Connection::Close
+        }
+        connection => connection,
+    };
+    // Streaming responses are written directly to the TCP stream by the worker.
+    // The length of the body is only known once it was written, so a length set
+    // by the handler is dropped.
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    if chunked {
+        response.headers_mut().insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+    }
+    worker_request
+        .supervisor
+        .send(WorkerResponse::StreamingResponse);
+    let head = response_head_to_vec(&mut response, &worker_request.config);
+    let result = stream
+        .write_all(&head)
+        .and_then(|()| stream_body.write_to(&mut stream, chunked));
+    let connection = match result {
+        Ok(()) => connection,
+        Err(err) => {
+            log_error(format!("Failed to send response: {err:?}"));
+            Connection::Close
+        }
+    };
     worker_request
         .supervisor
-        .send(WorkerResponse::Response(response, connection));
+        .send(WorkerResponse::StreamedResponse(connection));
 }
 
-fn response_to_vec(mut response: Response) -> Vec<u8> {
-    let mut response_buffer = Vec::new();
+/// Forwards panics of a worker with `report`, before the worker fails.
+///
+/// This lets the supervisor tell panics apart from workers that were stopped
+/// for other reasons, such as their resource limits.
+pub(crate) fn set_panic_hook<F>(request_line: Arc<Mutex<Option<String>>>, report: F)
+where
+    F: Fn(WorkerPanic) + Send + Sync + 'static,
+{
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "Box<dyn Any>".to_string(),
+            },
+        };
+        let request_line = request_line
+            .lock()
+            .ok()
+            .and_then(|request_line| request_line.clone());
+        report(WorkerPanic {
+            message,
+            location: info.location().map(|location| location.to_string()),
+            request_line,
+        });
+        default_hook(info);
+    }));
+}
+
+/// Returns the failure of a worker that failed before responding, logging
+/// the reason.
+///
+/// `limited` tells if the worker was running with resource limits.
+pub(crate) fn worker_failure(panicked: Option<&WorkerPanic>, limited: bool) -> Failure {
+    match panicked {
+        Some(panic) => {
+            log_error(format!("Worker process panicked: {panic}"));
+            Failure::new(FailureKind::Panic, StatusCode::INTERNAL_SERVER_ERROR)
+                .with_message(&panic.message)
+        }
+        // Link failures don't carry a reason, so without a panic report the cause
+        // is unknown. With resource limits, exceeding them is the likely one.
+        None if limited => {
+            log_error("Worker process failed without panicking, possibly resource limits");
+            Failure::new(FailureKind::ResourceLimit, StatusCode::SERVICE_UNAVAILABLE)
+        }
+        None => {
+            log_error("Worker process failed without panicking");
+            Failure::new(FailureKind::Internal, StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Renders the response to a failure with the error handler.
+fn render_failure(
+    (failure, error_handler, config, supervisor): (
+        Failure,
+        ErrorHandler,
+        ServerConfig,
+        SupervisorHandle,
+    ),
+    _: Mailbox<()>,
+) {
+    let response = (error_handler.get())(failure);
+    supervisor.send(WorkerResponse::FailureResponse(response_to_vec(
+        response, &config,
+    )));
+}
+
+/// Checks if the connection should be kept alive after the request.
+///
+/// HTTP/1.1 connections are kept alive unless the client sends
+/// `Connection: close`, while HTTP/1.0 connections are only kept alive with
+/// `Connection: keep-alive`.
+fn is_keep_alive(version: Version, headers: &HeaderMap) -> bool {
+    if version == Version::HTTP_10 {
+        has_connection_option(headers, "keep-alive")
+    } else {
+        !has_connection_option(headers, "close")
+    }
+}
+
+/// Checks if any `Connection` header contains the `option`.
+fn has_connection_option(headers: &HeaderMap, option: &str) -> bool {
+    headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(option))
+}
 
+/// Returns the maximum time for receiving a request.
+///
+/// A new connection is expected to send the request right away, while kept
+/// alive connections can stay idle before the next request starts.
+fn read_timeout(config: &ServerConfig, keep_alive: bool) -> Duration {
+    if keep_alive {
+        config.keep_alive_timeout + config.header_read_timeout
+    } else {
+        config.header_read_timeout
+    }
+}
+
+/// Returns the status code of the response to a request that failed to parse.
+///
+/// If the TCP stream was closed, no response can be sent and `None` is
+/// returned.
+fn parse_error_status(err: &ParseRequestError) -> Option<StatusCode> {
+    match err {
+        ParseRequestError::TcpStreamClosed | ParseRequestError::TcpStreamClosedWithoutData => None,
+        ParseRequestError::TooManyHeaders | ParseRequestError::HeadersTooLarge => {
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        }
+        ParseRequestError::ReadTimeout => Some(StatusCode::REQUEST_TIMEOUT),
+        ParseRequestError::UriTooLong => Some(StatusCode::URI_TOO_LONG),
+        ParseRequestError::RequestTooLarge => Some(StatusCode::PAYLOAD_TOO_LARGE),
+        ParseRequestError::ExpectationFailed => Some(StatusCode::EXPECTATION_FAILED),
+        ParseRequestError::HttpParseError(_)
+        | ParseRequestError::InvalidUri
+        | ParseRequestError::InvalidHeader
+        | ParseRequestError::InvalidContentLength
+        | ParseRequestError::DuplicateContentLength
+        | ParseRequestError::ContentLengthWithTransferEncoding
+        | ParseRequestError::UnsupportedTransferEncoding
+        | ParseRequestError::ObsoleteLineFolding
+        | ParseRequestError::InvalidChunkedBody
+        | ParseRequestError::UnknownMethod => Some(StatusCode::BAD_REQUEST),
+        #[cfg(feature = "http2")]
+        ParseRequestError::Http2Preface => None,
+    }
+}
+
+pub(crate) fn response_to_vec(mut response: Response, config: &ServerConfig) -> Vec<u8> {
+    // The length of the body is known, so it replaces any length set by the
+    // handler.
     let content_length = response.body().len();
-    *response.version_mut() = Version::HTTP_11;
     response
         .headers_mut()
-        .append(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+
+    let mut response_buffer = response_head_to_vec(&mut response, config);
+    response_buffer.extend(response.body());
+
+    response_buffer
+}
+
+/// Serializes the status line and headers of the response.
+fn response_head_to_vec(response: &mut Response, config: &ServerConfig) -> Vec<u8> {
+    let mut response_buffer = Vec::new();
+
+    insert_server_headers(response.headers_mut(), config);
+
+    // Only HTTP/1.0 and HTTP/1.1 responses can be written
+    if response.version() != Version::HTTP_10 {
+        *response.version_mut() = Version::HTTP_11;
+    }
 
     // writing status line
     response_buffer.extend(
@@ -235,19 +843,84 @@ fn response_to_vec(mut response: Response) -> Vec<u8> {
     );
     // writing headers
     for (key, value) in response.headers().iter() {
-        if let Ok(value) = String::from_utf8(value.as_ref().to_vec()) {
-            response_buffer.extend(format!("{key}: {value}\r\n").as_bytes());
-        }
+        response_buffer.extend(key.as_str().as_bytes());
+        response_buffer.extend(b": ");
+        response_buffer.extend(value.as_bytes());
+        response_buffer.extend(b"\r\n");
     }
     // separator between header and data
     response_buffer.extend("\r\n".as_bytes());
-    response_buffer.extend(response.body());
 
     response_buffer
 }
 
+/// Rewrites a serialized response to close the connection, replacing the
+/// `Connection` header set by the worker.
+fn close_connection(response: &[u8]) -> Vec<u8> {
+    // The head ends before the empty line separating it from the body
+    let head_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map_or(response.len(), |position| position + 2);
+    let mut closed = Vec::with_capacity(response.len() + 19);
+    for line in response[..head_end].split_inclusive(|&byte| byte == b'\n') {
+        if !line.starts_with(b"connection:") {
+            closed.extend(line);
+        }
+    }
+    closed.extend(b"connection: close\r\n");
+    closed.extend(&response[head_end..]);
+    closed
+}
+
+/// Adds the `Date` and `Server` headers, unless the handler already set them.
+pub(crate) fn insert_server_headers(headers: &mut HeaderMap, config: &ServerConfig) {
+    if config.date_header && !headers.contains_key(header::DATE) {
+        headers.insert(header::DATE, date_header());
+    }
+    if let Some(server) = &config.server_header {
+        if !headers.contains_key(header::SERVER) {
+            match HeaderValue::from_str(server) {
+                Ok(server) => {
+                    headers.insert(header::SERVER, server);
+                }
+                Err(_) => log_warn(format!("Invalid server header: {server:?}")),
+            }
+        }
+    }
+}
+
+thread_local! {
+    static DATE: RefCell<Option<(u64, HeaderValue)>> = RefCell::new(None);
+}
+
+/// Returns the value of the `Date` header for the current time.
+///
+/// The value only changes every second, so it's formatted once per second and
+/// cached.
+fn date_header() -> HeaderValue {
+    let now = SystemTime::now();
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    DATE.with(|date| {
+        let mut date = date.borrow_mut();
+        match &*date {
+            Some((cached_secs, value)) if *cached_secs == secs => value.clone(),
+            _ => {
+                let mut values = Vec::with_capacity(1);
+                headers::Date::from(now).encode(&mut values);
+                let value = values.pop().expect("date header is encoded");
+                *date = Some((secs, value.clone()));
+                value
+            }
+        }
+    })
+}
+
 #[cfg(feature = "logging")]
-fn log_request(request: &Request<Body>) {
+pub(crate) fn log_request(request: &Request<Body>) {
     let method_string = match *request.method() {
         http::Method::GET => ansi_term::Color::Green.normal(),
         http::Method::POST => ansi_term::Color::Blue.normal(),
@@ -273,12 +946,20 @@ fn log_request(request: &Request<Body>) {
 }
 
 #[cfg(not(feature = "logging"))]
-fn log_request(_request: &Request<Body>) {}
+pub(crate) fn log_request(_request: &Request<Body>) {}
 
 #[cfg(feature = "logging")]
-fn log_error(err: impl std::fmt::Display) {
+pub(crate) fn log_error(err: impl std::fmt::Display) {
     lunatic_log::error!("{err}");
 }
 
 #[cfg(not(feature = "logging"))]
-fn log_error(_err: impl std::fmt::Display) {}
+pub(crate) fn log_error(_err: impl std::fmt::Display) {}
+
+#[cfg(feature = "logging")]
+pub(crate) fn log_warn(warning: impl std::fmt::Display) {
+    lunatic_log::warn!("{warning}");
+}
+
+#[cfg(not(feature = "logging"))]
+pub(crate) fn log_warn(_warning: impl std::fmt::Display) {}
@@ -10,7 +10,6 @@ use http::header::{
 };
 use http::StatusCode;
 use lunatic::function::FuncRef;
-use lunatic::net::TcpStream;
 use lunatic::{Mailbox, Process};
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize};
@@ -22,14 +21,14 @@ pub use tungstenite::Message;
 use crate::extract::FromRequest;
 use crate::response::{IntoResponse, Response};
 use crate::supervisor::{Connection, SupervisorResponse};
-use crate::RequestContext;
+use crate::{RequestContext, Stream};
 
 /// A websocket connection.
 #[derive(Debug)]
-pub struct WebSocketConnection(tungstenite::protocol::WebSocket<TcpStream>);
+pub struct WebSocketConnection(tungstenite::protocol::WebSocket<Stream>);
 
-impl From<tungstenite::protocol::WebSocket<TcpStream>> for WebSocketConnection {
-    fn from(conn: tungstenite::protocol::WebSocket<TcpStream>) -> Self {
+impl From<tungstenite::protocol::WebSocket<Stream>> for WebSocketConnection {
+    fn from(conn: tungstenite::protocol::WebSocket<Stream>) -> Self {
         WebSocketConnection(conn)
     }
 }
@@ -115,7 +114,7 @@ impl Clone for WebSocketConnection {
 }
 
 impl ops::Deref for WebSocketConnection {
-    type Target = tungstenite::protocol::WebSocket<TcpStream>;
+    type Target = tungstenite::protocol::WebSocket<Stream>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -147,7 +146,7 @@ impl<'de> Deserialize<'de> for WebSocketConnection {
     {
         #[derive(Deserialize)]
         struct WebSocketConnectionVisitor {
-            stream: TcpStream,
+            stream: Stream,
             config: WebSocketConfig,
         }
 
@@ -178,7 +177,7 @@ impl<'de> Deserialize<'de> for WebSocketConnection {
 /// }
 /// ```
 pub struct WebSocket {
-    stream: TcpStream,
+    stream: Stream,
     websocket_key: Vec<u8>,
 }
 
@@ -236,6 +235,11 @@ impl FromRequest for WebSocket {
     type Rejection = WebSocketRejection;
 
     fn from_request(req: &mut RequestContext) -> Result<Self, Self::Rejection> {
+        // HTTP/2 connections are shared by multiple streams and can't be taken over
+        if req.version() >= http::Version::HTTP_2 {
+            return Err(WebSocketRejection::Http2NotSupported);
+        }
+
         // Connection must be Upgrade
         let mut connection_headers = req
             .headers()
@@ -379,6 +383,8 @@ pub enum WebSocketRejection {
     UnsupportedHttpMethod,
     /// Unsupported websocket version.
     UnsupportedWebSocketVersion,
+    /// Websockets over HTTP/2 are not supported.
+    Http2NotSupported,
 }
 
 impl IntoResponse for WebSocketRejection {
@@ -405,6 +411,10 @@ impl IntoResponse for WebSocketRejection {
                 StatusCode::NOT_IMPLEMENTED,
                 "websocket version not supported",
             ),
+            WebSocketRejection::Http2NotSupported => (
+                StatusCode::NOT_IMPLEMENTED,
+                "websockets over http/2 are not supported",
+            ),
         };
 
         (status, body).into_response()
@@ -87,6 +87,12 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// Routes are matched in the order they're defined. A route which can never
+/// match is a compile error, either because it's a duplicate of an earlier
+/// route with the same method, or because it's shadowed by one, such as
+/// `GET "/users/new"` after `GET "/users/:id"`. Routes after a route with a
+/// guard are never considered unreachable.
+///
 /// ## Methods
 ///
 /// The supported methods are:
@@ -99,6 +105,115 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 /// - OPTIONS
 /// - PATCH
 ///
+/// `HEAD` requests without a matching `HEAD` route are handled by the `GET`
+/// routes, and the body of the response is not sent. This can be disabled for
+/// a router and the routers nested in it with the `#![no_auto_head]` attribute.
+///
+/// ```ignore
+/// router! {
+///     #![no_auto_head]
+///
+///     GET "/" => index_handler
+/// }
+/// ```
+///
+/// Requests to a path which only has routes with other methods are answered
+/// with `405 Method Not Allowed`, listing the methods in the `Allow` header.
+/// `OPTIONS` requests to these paths are answered with the `Allow` header
+/// too, unless there is an `OPTIONS` route for the path. Requests rejected by
+/// the guards of a route with their method fall through to the catch-all.
+///
+/// # Params
+///
+/// Path segments starting with `:` are captured as params.
+///
+/// A constraint can be added to a param within `<` and `>`. It's either a type,
+/// which the param must parse as, or a regular expression the whole param must
+/// match. Params which don't match fall through to the next route, instead of
+/// being rejected by the handler.
+///
+/// Constraints which are paths, such as `u64` or `uuid::Uuid`, are types, so a
+/// regular expression matching a single word must be wrapped in a group, eg.
+/// `(en)`. The constraint ends at the first `>`, so regular expressions can't
+/// contain `>`.
+///
+/// ```ignore
+/// router! {
+///     GET "/users/:id<u64>" => user_by_id
+///     GET "/users/:name<[a-z-]+>" => user_by_name
+/// }
+/// ```
+///
+/// Params ending with `?` are optional, and the route matches with or without
+/// the segment.
+///
+/// ```ignore
+/// router! {
+///     GET "/posts/:id/:format?" => post
+/// }
+/// ```
+///
+/// # Wildcards
+///
+/// A `*` at the end of the path matches the remainder of the path, which can
+/// be extracted with `Splat`. A named wildcard, such as `*path`, is captured
+/// as a param too.
+///
+/// ```ignore
+/// router! {
+///     GET "/files/*path" => file
+/// }
+/// ```
+///
+/// # Named routes
+///
+/// Routes can be named with `as`, and a module can be declared for the router
+/// at the start of the macro. The macro then expands to the module, with a
+/// `router` function returning the router, and a function building the url of
+/// each named route.
+///
+/// The url functions take an argument for each param, including the params of
+/// sub-router prefixes, and percent-encode the values. Params with a type
+/// constraint take the type, and optional params take an `Option`. The url
+/// functions panic if a value doesn't match the regular expression constraint
+/// of its param.
+///
+/// ```ignore
+/// router! {
+///     pub mod urls;
+///
+///     GET "/users/:id<u64>" as user_show => user_show
+///     "/posts" => {
+///         GET "/:slug/:format?" as post_show => post_show
+///     }
+/// }
+///
+/// assert_eq!(urls::user_show(42), "/users/42");
+/// assert_eq!(urls::post_show("hello world", None), "/posts/hello%20world");
+///
+/// Application::new(urls::router())
+/// ```
+///
+/// The module imports everything from its parent module, so the router must
+/// be declared at module level, rather than inside a function.
+///
+/// # Route table
+///
+/// The macro generates a static table of the routes, with the method, full
+/// path, handler, guards and middleware of each route. Handlers, guards and
+/// middleware are named by the last segment of their path, eg. `show` for
+/// `users::show::<User>`. The table is available from `Application::routes`,
+/// or as `ROUTES` in the module of a router, and can be logged when the server
+/// starts with `Application::with_route_logging`.
+///
+/// ```ignore
+/// Application::new(router! {
+///     GET "/" => index
+/// })
+/// .with_route_logging()
+/// .serve("0.0.0.0:3000")
+/// ```
+///
 /// # Sub-routers
 ///
 /// Routers can be nested to create more complex routing.
@@ -174,6 +289,10 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 ///
 /// > `{`
 /// >
+/// > &nbsp;&nbsp;&nbsp;&nbsp;[_RouterAttribute_]*
+/// >
+/// > &nbsp;&nbsp;&nbsp;&nbsp;[_RouterModule_]﹖
+/// >
 /// > &nbsp;&nbsp;&nbsp;&nbsp;[_RouterMiddleware_]﹖ `;`
 /// >
 /// > &nbsp;&nbsp;&nbsp;&nbsp;[_RouterItem_]*
@@ -182,9 +301,17 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 /// >
 /// > `}`
 ///
+/// ##### RouterAttribute
+///
+/// > `#![no_auto_head]`
+///
+/// ##### RouterModule
+///
+/// > [Visibility]﹖ `mod` [IDENTIFIER] `;`
+///
 /// ##### RouterItem
 ///
-/// > [_RouterMethod_]﹖ [STRING_LITERAL] [_RouterIfStmt_]﹖
+/// > [_RouterMethod_]﹖ [STRING_LITERAL] (`as` [IDENTIFIER])﹖ [_RouterIfStmt_]﹖
 /// > [_RouterMiddleware_] `=>` [_RouterItemValue_]
 ///
 /// ##### RouterItemValue
@@ -212,6 +339,8 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 /// > `_` `=>` [_RouterItemValue_]
 ///
 /// [_RouterDefinition_]: #routerdefinition
+/// [_RouterAttribute_]: #routerattribute
+/// [_RouterModule_]: #routermodule
 /// [_RouterMiddleware_]: #routermiddleware
 /// [_RouterMiddlewareItem_]: #routermiddlewareitem
 /// [_RouterItem_]: #routeritem
@@ -223,10 +352,11 @@ pub fn named_param(input: TokenStream) -> TokenStream {
 /// [IDENTIFIER]: https://doc.rust-lang.org/reference/identifiers.html
 /// [STRING_LITERAL]: https://doc.rust-lang.org/reference/tokens.html#string-literals
 /// [Expression]: https://doc.rust-lang.org/reference/expressions.html
+/// [Visibility]: https://doc.rust-lang.org/reference/visibility-and-privacy.html
 #[proc_macro]
 pub fn router(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as Router);
-    input.expand().into()
+    input.expand_root().into()
 }
 
 /// The static router can be used to serve static files within a folder.
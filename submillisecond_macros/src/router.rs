@@ -1,25 +1,34 @@
+pub use conflicts::*;
 pub use item_catch_all::*;
+pub use item_module::*;
 pub use item_route::*;
 pub use item_with_middleware::*;
 pub use method::*;
+pub use param_constraint::*;
 pub use router_trie::*;
 pub use trie::*;
 
+mod conflicts;
 mod item_catch_all;
+mod item_module;
 mod item_route;
 mod item_with_middleware;
 mod method;
+mod param_constraint;
 mod router_trie;
 mod trie;
 
 use proc_macro2::TokenStream;
+use quote::ToTokens;
 use syn::parse::{Parse, ParseStream};
-use syn::{LitStr, Token};
+use syn::{Attribute, LitStr, Token};
 
 use crate::hquote;
 
 #[derive(Clone, Debug)]
 pub struct Router {
+    auto_head: bool,
+    module: Option<ItemModule>,
     middleware: Option<ItemWithMiddleware>,
     routes: Vec<ItemRoute>,
     catch_all: Option<ItemCatchAll>,
@@ -27,7 +36,33 @@ pub struct Router {
 }
 
 impl Router {
+    /// Expand the root router, into a module if one is declared.
+    pub fn expand_root(&self) -> TokenStream {
+        match &self.module {
+            Some(module) => module.expand(self),
+            None => match self.first_named_route() {
+                Some(name) => syn::Error::new(
+                    name.ident.span(),
+                    "named routes require a module for the router, eg. `pub mod urls;`",
+                )
+                .into_compile_error(),
+                None => {
+                    let routes = self.expand_routes();
+                    self.expand_with_routes(hquote! {{
+                        static ROUTES: &[::submillisecond::routes::RouteInfo] = #routes;
+                        ROUTES
+                    }})
+                }
+            },
+        }
+    }
+
     pub fn expand(&self) -> TokenStream {
+        self.expand_with_routes(hquote! { &[] })
+    }
+
+    /// Expand the router, with the table of its `routes`.
+    pub fn expand_with_routes(&self, routes: TokenStream) -> TokenStream {
         let trie = RouterTrie::new(self);
         let inner = trie.expand();
 
@@ -40,12 +75,100 @@ impl Router {
         hquote! {(|| {
             #( #inits; )*
 
-            (|mut req: ::submillisecond::RequestContext| -> ::submillisecond::response::Response {
-                #inner
-            }) as fn(_) -> _
+            ::submillisecond::RouterHandler::new(
+                |mut req: ::submillisecond::RequestContext| -> ::submillisecond::response::Response {
+                    #inner
+                },
+                #routes,
+            )
         }) as ::submillisecond::Router}
     }
 
+    /// Expand the static table of the routes, with the full path, guards and
+    /// middleware of each route.
+    pub fn expand_routes(&self) -> TokenStream {
+        let mut routes = Vec::new();
+        self.collect_routes("", &[], &[], &mut routes);
+        hquote! { &[ #( #routes ),* ] }
+    }
+
+    fn collect_routes(
+        &self,
+        prefix: &str,
+        guards: &[String],
+        middleware: &[String],
+        routes: &mut Vec<TokenStream>,
+    ) {
+        let mut middleware = middleware.to_vec();
+        if let Some(with) = &self.middleware {
+            middleware.extend(with.items.iter().map(expr_name));
+        }
+
+        for route in &self.routes {
+            let path = format!("{prefix}{}", route.path.value());
+            let mut guards = guards.to_vec();
+            if let Some(guard) = &route.guard {
+                guards.push(expr_name(&guard.guard));
+            }
+            let mut middleware = middleware.clone();
+            if let Some(with) = &route.middleware {
+                middleware.extend(with.items.iter().map(expr_name));
+            }
+
+            match &route.handler {
+                ItemHandler::Expr(handler) => {
+                    let method = match route.method {
+                        Some(method) => {
+                            let method = method.to_string();
+                            hquote! { ::std::option::Option::Some(#method) }
+                        }
+                        None => hquote! { ::std::option::Option::None },
+                    };
+                    let handler = expr_name(handler);
+                    routes.push(hquote! {
+                        ::submillisecond::routes::RouteInfo {
+                            method: #method,
+                            path: #path,
+                            handler: #handler,
+                            guards: &[ #( #guards ),* ],
+                            middleware: &[ #( #middleware ),* ],
+                        }
+                    });
+                }
+                ItemHandler::SubRouter(router) => {
+                    router.collect_routes(&path, &guards, &middleware, routes);
+                }
+            }
+        }
+    }
+
+    fn first_named_route(&self) -> Option<&ItemName> {
+        self.routes.iter().find_map(|route| match &route.handler {
+            ItemHandler::Expr(_) => route.name.as_ref(),
+            ItemHandler::SubRouter(router) => router.first_named_route(),
+        })
+    }
+
+    /// Disable automatic `HEAD` handling for this router and every router
+    /// nested in it.
+    fn disable_auto_head(&mut self) {
+        self.auto_head = false;
+        let nested = self
+            .routes
+            .iter_mut()
+            .map(|route| &mut route.handler)
+            .chain(
+                self.catch_all
+                    .iter_mut()
+                    .map(|catch_all| &mut *catch_all.handler),
+            );
+        for handler in nested {
+            if let ItemHandler::SubRouter(router) = handler {
+                router.disable_auto_head();
+            }
+        }
+    }
+
     fn handlers(&mut self) -> Vec<syn::Expr> {
         self.routes
             .iter_mut()
@@ -60,8 +183,48 @@ impl Router {
     }
 }
 
+/// Name of an expression in the route table, with paths shortened to the ident
+/// of their last segment, eg. `show` for `users::show::<User>`.
+fn expr_name(expr: &syn::Expr) -> String {
+    match expr {
+        syn::Expr::Path(expr) => path_name(&expr.path),
+        syn::Expr::Struct(expr) => path_name(&expr.path),
+        syn::Expr::Call(expr) if expr.args.is_empty() => format!("{}()", expr_name(&expr.func)),
+        syn::Expr::Call(expr) => format!("{}(..)", expr_name(&expr.func)),
+        syn::Expr::Binary(expr) => format!(
+            "{} {} {}",
+            expr_name(&expr.left),
+            expr.op.to_token_stream(),
+            expr_name(&expr.right)
+        ),
+        syn::Expr::Paren(expr) => format!("({})", expr_name(&expr.expr)),
+        expr => expr.to_token_stream().to_string(),
+    }
+}
+
+fn path_name(path: &syn::Path) -> String {
+    path.segments
+        .last()
+        .map(|segment| segment.ident.to_string())
+        .unwrap_or_default()
+}
+
 impl Parse for Router {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut auto_head = true;
+        for attr in input.call(Attribute::parse_inner)? {
+            if attr.path.is_ident("no_auto_head") && attr.tokens.is_empty() {
+                auto_head = false;
+            } else {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "unknown router attribute, expected `#![no_auto_head]`",
+                ));
+            }
+        }
+
+        let module = ItemModule::peek(input).then(|| input.parse()).transpose()?;
+
         let middleware = if input.peek(with) {
             let middleware = input.parse()?;
             let _: Token![;] = input.parse()?;
@@ -78,15 +241,22 @@ impl Parse for Router {
             routes.push(input.parse()?);
         }
 
+        check_conflicts(&routes)?;
+
         let catch_all = input.peek(Token![_]).then(|| input.parse()).transpose()?;
 
         let mut router = Router {
+            auto_head,
+            module,
             middleware,
             routes,
             catch_all,
             inits: vec![],
         };
 
+        if !router.auto_head {
+            router.disable_auto_head();
+        }
         router.inits = router.handlers();
 
         Ok(router)
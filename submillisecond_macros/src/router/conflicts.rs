@@ -0,0 +1,282 @@
+use regex::Regex;
+use syn::spanned::Spanned;
+
+use super::item_route::ItemRoute;
+use super::param_constraint::ParamConstraint;
+use super::router_trie::RouterTrie;
+
+/// Check that no route is a duplicate of, or is shadowed by, an earlier route
+/// with the same method, or by a sub router.
+///
+/// Sub routers are matched before the routes with a method, so a route is
+/// shadowed by the prefix of a sub router wherever the sub router is declared.
+/// Routes after a route with a guard are never unreachable, since the guard
+/// might not pass.
+pub fn check_conflicts(routes: &[ItemRoute]) -> syn::Result<()> {
+    let mut errors: Option<syn::Error> = None;
+    for (i, route) in routes.iter().enumerate() {
+        let subrouter_error = route.method.and_then(|_| {
+            routes
+                .iter()
+                .filter(|subrouter| subrouter.method.is_none() && subrouter.guard.is_none())
+                .find_map(|subrouter| subrouter_conflict(subrouter, route))
+        });
+        let error = subrouter_error.or_else(|| {
+            routes[..i]
+                .iter()
+                .filter(|earlier| {
+                    earlier.guard.is_none()
+                        && earlier.method.map(|method| method.to_string())
+                            == route.method.map(|method| method.to_string())
+                })
+                .find_map(|earlier| conflict(earlier, route))
+        });
+
+        if let Some(error) = error {
+            match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            }
+        }
+    }
+
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(()),
+    }
+}
+
+/// Check if `route` is shadowed by the prefix of `subrouter`.
+fn subrouter_conflict(subrouter: &ItemRoute, route: &ItemRoute) -> Option<syn::Error> {
+    let prefixes = RouterTrie::expand_optional_segments(&subrouter.path.value());
+    let paths = RouterTrie::expand_optional_segments(&route.path.value());
+    if !paths
+        .iter()
+        .all(|path| prefixes.iter().any(|prefix| prefix_shadows(prefix, path)))
+    {
+        return None;
+    }
+
+    let method = route
+        .method
+        .map(|method| format!("{method} "))
+        .unwrap_or_default();
+    let mut error = syn::Error::new(
+        route.path.span(),
+        format!(
+            "unreachable route `{method}{}`, it's shadowed by a sub router",
+            route.path.value()
+        ),
+    );
+    error.combine(syn::Error::new(
+        subrouter.path.span(),
+        "shadowed by this sub router",
+    ));
+    Some(error)
+}
+
+/// Check if `route` is a duplicate of, or is shadowed by, `earlier`.
+fn conflict(earlier: &ItemRoute, route: &ItemRoute) -> Option<syn::Error> {
+    let earlier_paths = RouterTrie::expand_optional_segments(&earlier.path.value());
+    let paths = RouterTrie::expand_optional_segments(&route.path.value());
+    let method = route
+        .method
+        .map(|method| format!("{method} "))
+        .unwrap_or_default();
+
+    let (message, note) = if paths.iter().all(|path| earlier_paths.contains(path)) {
+        (
+            format!("duplicate route `{method}{}`", route.path.value()),
+            "first defined here",
+        )
+    } else if paths.iter().all(|path| {
+        earlier_paths
+            .iter()
+            .any(|earlier_path| shadows(earlier_path, path))
+    }) {
+        (
+            format!(
+                "unreachable route `{method}{}`, it's shadowed by an earlier route",
+                route.path.value()
+            ),
+            "shadowed by this route",
+        )
+    } else {
+        return None;
+    };
+
+    let mut error = syn::Error::new(route.path.span(), message);
+    error.combine(syn::Error::new(earlier.path.span(), note));
+    Some(error)
+}
+
+/// Check if every path matching `path` matches `earlier` too.
+fn shadows(earlier: &str, path: &str) -> bool {
+    let mut earlier_segments = earlier.split('/');
+    let mut segments = path.split('/');
+    loop {
+        match (earlier_segments.next(), segments.next()) {
+            (None, None) => return true,
+            (Some(earlier_segment), Some(segment)) => {
+                if let Some((prefix, _)) = earlier_segment.split_once('*') {
+                    let rest = [segment]
+                        .into_iter()
+                        .chain(segments)
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    return rest.starts_with(prefix);
+                }
+
+                if !segment_shadows(earlier_segment, segment) {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Check if every path matching `path` starts with a path matching the sub
+/// router `prefix`.
+fn prefix_shadows(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.strip_suffix('/').unwrap_or(prefix);
+    let mut segments = path.split('/');
+    prefix.split('/').all(|prefix_segment| {
+        segments
+            .next()
+            .map_or(false, |segment| segment_shadows(prefix_segment, segment))
+    })
+}
+
+/// Check if every segment matching `segment` matches `earlier` too.
+fn segment_shadows(earlier: &str, segment: &str) -> bool {
+    if earlier == segment {
+        return true;
+    }
+
+    // only a param spanning the whole segment can match other segments
+    let constraint = match RouterTrie::capture_param_parts(earlier) {
+        Some(("", _, constraint, "")) => constraint,
+        _ => return false,
+    };
+
+    match constraint.map(ParamConstraint::parse) {
+        // an unconstrained param matches any segment
+        None => !segment.is_empty() && !segment.contains('*'),
+        // a regular expression can only be checked against a static segment
+        Some(Ok(ParamConstraint::Regex(pattern))) => {
+            !segment.contains([':', '*'])
+                && Regex::new(&pattern).map_or(false, |regex| regex.is_match(segment))
+        }
+        // the values a type parses from are unknown
+        Some(Ok(ParamConstraint::Type(_))) | Some(Err(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prefix_shadows, shadows};
+    use crate::router::Router;
+
+    fn parse_err(input: &str) -> Option<String> {
+        syn::parse_str::<Router>(input)
+            .err()
+            .map(|err| err.to_string())
+    }
+
+    #[test]
+    fn conflicting_routes() {
+        assert_eq!(
+            parse_err(r#"GET "/a" => a GET "/a" => b"#).as_deref(),
+            Some("duplicate route `GET /a`")
+        );
+        assert_eq!(
+            parse_err(r#"GET "/posts/:id/:format?" => a GET "/posts/:id" => b"#).as_deref(),
+            Some("duplicate route `GET /posts/:id`")
+        );
+        assert_eq!(
+            parse_err(r#"GET "/users/:id" => a GET "/users/new" => b"#).as_deref(),
+            Some("unreachable route `GET /users/new`, it's shadowed by an earlier route")
+        );
+        assert_eq!(
+            parse_err(r#""/:lang" => { GET "/" => a } "/api" => { GET "/" => b }"#).as_deref(),
+            Some("unreachable route `/api`, it's shadowed by an earlier route")
+        );
+
+        assert_eq!(
+            parse_err(r#"GET "/api/users" => a "/api" => { GET "/" => b }"#).as_deref(),
+            Some("unreachable route `GET /api/users`, it's shadowed by a sub router")
+        );
+
+        assert_eq!(parse_err(r#"GET "/a" => a POST "/a" => b"#), None);
+        assert_eq!(parse_err(r#"GET "/a" if guard => a GET "/a" => b"#), None);
+        assert_eq!(
+            parse_err(r#"GET "/users/new" => a GET "/users/:id" => b"#),
+            None
+        );
+        assert_eq!(
+            parse_err(r#"GET "/api/users" => a "/api" if guard => { GET "/" => b }"#),
+            None
+        );
+        assert_eq!(
+            parse_err(r#"GET "/about" => a "/api" => { GET "/" => b }"#),
+            None
+        );
+    }
+
+    #[test]
+    fn conflict_spans() {
+        let spans = |input: &str| -> Vec<(String, usize)> {
+            let err = syn::parse_str::<Router>(input).unwrap_err();
+            err.into_iter()
+                .map(|err| (err.to_string(), err.span().start().column))
+                .collect()
+        };
+
+        assert_eq!(
+            spans(r#"GET "/a" => a GET "/a" => b"#),
+            vec![
+                ("duplicate route `GET /a`".to_string(), 18),
+                ("first defined here".to_string(), 4),
+            ]
+        );
+        assert_eq!(
+            spans(r#"GET "/api/users" => a "/api" => { GET "/" => b }"#),
+            vec![
+                (
+                    "unreachable route `GET /api/users`, it's shadowed by a sub router".to_string(),
+                    4
+                ),
+                ("shadowed by this sub router".to_string(), 22),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_paths() {
+        assert!(shadows("/users/:id", "/users/new"));
+        assert!(shadows("/users/:id", "/users/:id<u64>"));
+        assert!(shadows("/users/:slug<[a-z]+>", "/users/new"));
+        assert!(shadows("/files/*", "/files/a/b"));
+        assert!(shadows("/any-*", "/any-thing"));
+
+        assert!(!shadows("/users/new", "/users/:id"));
+        assert!(!shadows("/users/:id<u64>", "/users/new"));
+        assert!(!shadows("/users/:slug<[a-z]+>", "/users/New"));
+        assert!(!shadows("/users/:id", "/users/:id/posts"));
+        assert!(!shadows("/:lang/about", "/about"));
+        assert!(!shadows("/users/:id", "/users/*"));
+    }
+
+    #[test]
+    fn shadowed_prefixes() {
+        assert!(prefix_shadows("/api", "/api"));
+        assert!(prefix_shadows("/api", "/api/users/:id"));
+        assert!(prefix_shadows("/:lang", "/about"));
+        assert!(prefix_shadows("/", "/about"));
+
+        assert!(!prefix_shadows("/api", "/about"));
+        assert!(!prefix_shadows("/api", "/"));
+        assert!(!prefix_shadows("/:id<u64>", "/about"));
+    }
+}
@@ -0,0 +1,219 @@
+use lazy_static::lazy_static;
+use proc_macro2::{Span, TokenStream};
+use regex::Regex;
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, Token, Visibility};
+
+use super::item_route::{ItemHandler, ItemName, ItemRoute};
+use super::param_constraint::ParamConstraint;
+use super::Router;
+use crate::hquote;
+
+lazy_static! {
+    static ref URL_PART_RE: Regex = Regex::new(
+        r":(?P<param>[a-zA-Z_]+)(?:<(?P<constraint>[^>]+)>)?(?P<optional>\?)?|\*(?P<wildcard>[a-zA-Z_]*)$"
+    )
+    .unwrap();
+}
+
+/// `pub mod urls;`
+#[derive(Clone, Debug)]
+pub struct ItemModule {
+    pub vis: Visibility,
+    pub mod_token: Token![mod],
+    pub ident: Ident,
+    pub semi_token: Token![;],
+}
+
+impl ItemModule {
+    pub fn peek(input: ParseStream) -> bool {
+        input.peek(Token![mod]) || input.peek(Token![pub])
+    }
+
+    /// Expand the module with the router, the table of its routes, and a
+    /// function building the url of each named route.
+    pub fn expand(&self, router: &Router) -> TokenStream {
+        let ItemModule { vis, ident, .. } = self;
+        let routes_expanded = router.expand_routes();
+        let router_expanded = router.expand_with_routes(hquote! { ROUTES });
+
+        let mut named_routes = Vec::new();
+        collect_named_routes("", &router.routes, &mut named_routes);
+        let url_fns = named_routes
+            .into_iter()
+            .map(|(name, path)| expand_url_fn(name, &path));
+
+        hquote! {
+            #vis mod #ident {
+                #[allow(unused_imports)]
+                use super::*;
+
+                /// The routes of the router.
+                pub const ROUTES: &[::submillisecond::routes::RouteInfo] = #routes_expanded;
+
+                /// The router.
+                pub fn router() -> ::submillisecond::Router {
+                    #router_expanded
+                }
+
+                #( #url_fns )*
+            }
+        }
+    }
+}
+
+impl Parse for ItemModule {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(ItemModule {
+            vis: input.parse()?,
+            mod_token: input.parse()?,
+            ident: input.parse()?,
+            semi_token: input.parse()?,
+        })
+    }
+}
+
+/// Collect named routes with their full path, including the prefixes of sub
+/// routers.
+fn collect_named_routes<'r>(
+    prefix: &str,
+    routes: &'r [ItemRoute],
+    named_routes: &mut Vec<(&'r ItemName, String)>,
+) {
+    for route in routes {
+        let path = format!("{prefix}{}", route.path.value());
+        match &route.handler {
+            ItemHandler::Expr(_) => {
+                if let Some(name) = &route.name {
+                    named_routes.push((name, path));
+                }
+            }
+            ItemHandler::SubRouter(router) => {
+                collect_named_routes(&path, &router.routes, named_routes);
+            }
+        }
+    }
+}
+
+/// Expand a function building the url of a named route, with an argument for
+/// each param.
+///
+/// Params with a type constraint take the type, optional params take an
+/// [`Option`], and the values are percent-encoded. Values which don't match
+/// the regular expression constraint of their param panic, as the url wouldn't
+/// match the route.
+fn expand_url_fn(name: &ItemName, path: &str) -> TokenStream {
+    let mut args = Vec::new();
+    let mut pushes = Vec::new();
+    let mut last_end = 0;
+    for captures in URL_PART_RE.captures_iter(path) {
+        let whole = captures.get(0).unwrap();
+        let mut literal = &path[last_end..whole.start()];
+        last_end = whole.end();
+
+        let optional = captures.name("optional").is_some();
+        let separator = if optional {
+            literal.strip_suffix('/').map(|stripped| {
+                literal = stripped;
+                "/"
+            })
+        } else {
+            None
+        };
+        if !literal.is_empty() {
+            pushes.push(hquote! { url.push_str(#literal); });
+        }
+
+        let (param, encode) = match captures.name("wildcard") {
+            Some(wildcard) if wildcard.as_str().is_empty() => continue,
+            Some(wildcard) => (wildcard.as_str(), hquote! { encode_path }),
+            None => (&captures["param"], hquote! { encode }),
+        };
+        let name = param;
+        let param = param_ident(param);
+        let constraint = captures
+            .name("constraint")
+            .map(|c| ParamConstraint::parse(c.as_str()));
+        let ty = match &constraint {
+            Some(Ok(ParamConstraint::Type(ty))) => hquote! { #ty },
+            _ if optional => hquote! { &str },
+            _ => hquote! { impl ::std::fmt::Display },
+        };
+        let check = match &constraint {
+            Some(Ok(constraint @ ParamConstraint::Regex(_))) => {
+                let check = constraint.expand_check();
+                hquote! {
+                    ::std::assert!(
+                        #check,
+                        "`{}` doesn't match the constraint of the param `{}`",
+                        value,
+                        #name,
+                    );
+                }
+            }
+            _ => hquote! {},
+        };
+        let separator = separator.map(|separator| hquote! { url.push_str(#separator); });
+        let push = hquote! {
+            let value = #param.to_string();
+            let value = value.as_str();
+            #check
+            url.push_str(&::submillisecond::params::#encode(value));
+        };
+
+        if optional {
+            args.push(hquote! { #param: ::std::option::Option<#ty> });
+            pushes.push(hquote! {
+                if let ::std::option::Option::Some(#param) = #param {
+                    #separator
+                    #push
+                }
+            });
+        } else {
+            args.push(hquote! { #param: #ty });
+            pushes.push(push);
+        }
+    }
+    let literal = &path[last_end..];
+    if !literal.is_empty() {
+        pushes.push(hquote! { url.push_str(#literal); });
+    }
+
+    let ident = &name.ident;
+    let doc = format!("Url of the `{ident}` route, `{path}`.");
+
+    hquote! {
+        #[doc = #doc]
+        pub fn #ident(#( #args ),*) -> ::std::string::String {
+            let mut url = ::std::string::String::new();
+            #( #pushes )*
+            if url.is_empty() {
+                url.push('/');
+            }
+            url
+        }
+    }
+}
+
+/// Create the ident of a param, which is raw if the name is a keyword.
+fn param_ident(param: &str) -> Ident {
+    if syn::parse_str::<Ident>(param).is_ok() {
+        Ident::new(param, Span::mixed_site())
+    } else {
+        Ident::new_raw(param, Span::mixed_site())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::URL_PART_RE;
+
+    #[test]
+    fn url_parts() {
+        let parts: Vec<_> = URL_PART_RE
+            .find_iter("/posts/:id<u64>/:format?/*rest")
+            .map(|part| part.as_str())
+            .collect();
+        assert_eq!(parts, vec![":id<u64>", ":format?", "*rest"]);
+    }
+}
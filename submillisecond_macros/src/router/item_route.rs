@@ -2,16 +2,18 @@ use proc_macro2::TokenStream;
 use quote::{ToTokens, TokenStreamExt};
 use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
-use syn::{braced, token, Expr, LitStr, Path, Token};
+use syn::{braced, token, Expr, Ident, LitStr, Path, Token};
 
 use super::item_with_middleware::ItemWithMiddleware;
 use super::method::Method;
+use super::param_constraint::ParamConstraint;
 use super::with;
 use crate::hquote;
 use crate::router::Router;
 
 /// `"/abc" => sub_router`
 /// `GET "/abc" => handler`
+/// `GET "/abc" as name => handler`
 /// `GET "/abc" if guard => handler`
 /// `GET "/abc" use middleware => handler`
 /// `GET "/abc" if guard use middleware => handler`
@@ -19,6 +21,7 @@ use crate::router::Router;
 pub struct ItemRoute {
     pub method: Option<Method>,
     pub path: LitStr,
+    pub name: Option<ItemName>,
     pub guard: Option<ItemGuard>,
     pub middleware: Option<ItemWithMiddleware>,
     pub fat_arrow_token: Token![=>],
@@ -34,6 +37,11 @@ impl Parse for ItemRoute {
                 None
             },
             path: input.parse()?,
+            name: if input.peek(Token![as]) {
+                Some(input.parse()?)
+            } else {
+                None
+            },
             guard: if input.peek(Token![if]) {
                 Some(input.parse()?)
             } else {
@@ -57,9 +65,41 @@ impl Parse for ItemRoute {
             }
         }
 
+        if let (None, Some(name)) = (item_route.method, &item_route.name) {
+            return Err(syn::Error::new(
+                name.ident.span(),
+                "only routes with a method prefix can be named",
+            ));
+        }
+
+        if let ItemHandler::SubRouter(Router {
+            module: Some(module),
+            ..
+        }) = &item_route.handler
+        {
+            return Err(syn::Error::new(
+                module.ident.span(),
+                "a module can only be declared on the root router",
+            ));
+        }
+
         let path = item_route.path.value();
+        ParamConstraint::validate_path(&path)
+            .map_err(|err| syn::Error::new(item_route.path.span(), err))?;
+
+        if path
+            .split('/')
+            .any(|segment| segment.ends_with('?') && !segment.starts_with(':'))
+        {
+            return Err(syn::Error::new(
+                item_route.path.span(),
+                "only params can be optional, eg. `/:format?`",
+            ));
+        }
+
         if let Some(pos) = path.find('*') {
-            if pos < path.len() - 1 {
+            let name = &path[pos + 1..];
+            if !name.chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
                 return Err(syn::Error::new(
                     item_route.path.span(),
                     "wildcards must be placed at the end of the path",
@@ -78,6 +118,22 @@ impl Parse for ItemRoute {
     }
 }
 
+/// `as name`
+#[derive(Clone, Debug)]
+pub struct ItemName {
+    pub as_token: Token![as],
+    pub ident: Ident,
+}
+
+impl Parse for ItemName {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(ItemName {
+            as_token: input.parse()?,
+            ident: input.parse()?,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ItemGuard {
     pub if_token: Token![if],
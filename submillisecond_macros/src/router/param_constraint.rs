@@ -0,0 +1,103 @@
+use lazy_static::lazy_static;
+use proc_macro2::TokenStream;
+use regex::Regex;
+
+use crate::hquote;
+
+lazy_static! {
+    static ref CONSTRAINED_PARAM_RE: Regex =
+        Regex::new(r":(?P<param>[a-zA-Z_]+)<(?P<constraint>[^>]*)>").unwrap();
+}
+
+/// Constraint of a path param, eg. `:id<u64>` or `:slug<[a-z-]+>`.
+#[derive(Clone, Debug)]
+pub enum ParamConstraint {
+    /// The param must parse as the type.
+    Type(syn::Path),
+    /// The whole param must match the regular expression.
+    Regex(String),
+}
+
+impl ParamConstraint {
+    /// Parse a constraint, which is a type if it's a path, such as `u64` or
+    /// `uuid::Uuid`, and a regular expression otherwise.
+    ///
+    /// The constraint ends at the first `>`, so regular expressions can't
+    /// contain `>`.
+    pub fn parse(constraint: &str) -> Result<Self, String> {
+        if constraint.is_empty() {
+            return Err("constraint cannot be empty".to_string());
+        }
+
+        if let Ok(ty) = syn::parse_str::<syn::Path>(constraint) {
+            return Ok(ParamConstraint::Type(ty));
+        }
+
+        let pattern = format!("^(?:{constraint})$");
+        Regex::new(&pattern).map_err(|err| err.to_string())?;
+        Ok(ParamConstraint::Regex(pattern))
+    }
+
+    /// Validate the constraints of all params in a path.
+    pub fn validate_path(path: &str) -> Result<(), String> {
+        for captures in CONSTRAINED_PARAM_RE.captures_iter(path) {
+            let param = &captures["param"];
+            ParamConstraint::parse(&captures["constraint"])
+                .map_err(|err| format!("invalid constraint for param `{param}`: {err}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Expand the check of a param `value`.
+    pub fn expand_check(&self) -> TokenStream {
+        match self {
+            ParamConstraint::Type(ty) => hquote! {
+                value.parse::<#ty>().is_ok()
+            },
+            ParamConstraint::Regex(pattern) => hquote! {{
+                static PATTERN: ::submillisecond::reader::Pattern =
+                    ::submillisecond::reader::Pattern::new(#pattern);
+                PATTERN.is_match(value)
+            }},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParamConstraint;
+
+    #[test]
+    fn parse_constraints() {
+        assert!(matches!(
+            ParamConstraint::parse("u64"),
+            Ok(ParamConstraint::Type(_))
+        ));
+        assert!(matches!(
+            ParamConstraint::parse("uuid::Uuid"),
+            Ok(ParamConstraint::Type(_))
+        ));
+        assert!(matches!(
+            ParamConstraint::parse("(en)"),
+            Ok(ParamConstraint::Regex(pattern)) if pattern == "^(?:(en))$"
+        ));
+        assert!(matches!(
+            ParamConstraint::parse("en|fr"),
+            Ok(ParamConstraint::Regex(pattern)) if pattern == "^(?:en|fr)$"
+        ));
+        assert!(matches!(
+            ParamConstraint::parse("[a-z-]+"),
+            Ok(ParamConstraint::Regex(pattern)) if pattern == "^(?:[a-z-]+)$"
+        ));
+        assert!(ParamConstraint::parse("[a-z").is_err());
+        assert!(ParamConstraint::parse("").is_err());
+    }
+
+    #[test]
+    fn validate_path() {
+        assert!(ParamConstraint::validate_path("/users/:id<u64>/:slug<[a-z-]+>").is_ok());
+        assert!(ParamConstraint::validate_path("/users/:id").is_ok());
+        assert!(ParamConstraint::validate_path("/users/:id<(>").is_err());
+    }
+}
@@ -7,17 +7,33 @@ use super::item_catch_all::ItemCatchAll;
 use super::item_route::{ItemGuard, ItemHandler, ItemRoute};
 use super::item_with_middleware::ItemWithMiddleware;
 use super::method::Method;
+use super::param_constraint::ParamConstraint;
 use super::trie::{Node, Trie};
 use super::Router;
 use crate::hquote;
 
 lazy_static! {
-    static ref RE: Regex =
-        Regex::new(r"(?P<lit_prefix>[^:]*):(?P<param>[a-zA-Z_]+)(?P<lit_suffix>.*)").unwrap();
+    static ref RE: Regex = Regex::new(
+        r"(?P<lit_prefix>[^:]*):(?P<param>[a-zA-Z_]+)(?:<(?P<constraint>[^>]+)>)?(?P<lit_suffix>.*)"
+    )
+    .unwrap();
 }
 
-#[derive(Debug, Default)]
+/// Methods in the order of the `Allow` header, with the bits of
+/// [`RouterTrie::allowed`] in the same order.
+const ALLOWED_METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"];
+
+/// Bit of the method `name` in [`RouterTrie::allowed`].
+fn method_bit(name: &str) -> u8 {
+    let i = ALLOWED_METHODS.iter().position(|method| *method == name);
+    1 << i.unwrap()
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct RouterTrie<'r> {
+    auto_head: bool,
+    // paths of the method routes, with the bits of their allowed methods
+    allowed: Vec<(String, u8)>,
     catch_all: Option<&'r ItemCatchAll>,
     middleware: Option<&'r ItemWithMiddleware>,
     // trie to collect subrouters
@@ -69,6 +85,7 @@ impl<'r> RouterTrie<'r> {
     /// Create a new [`RouterTrie`] from an instance of [`RouterTree`].
     pub fn new(router_tree: &'r Router) -> Self {
         let mut trie = RouterTrie {
+            auto_head: router_tree.auto_head,
             catch_all: router_tree.catch_all.as_ref(),
             middleware: router_tree.middleware.as_ref(),
             ..Default::default()
@@ -106,42 +123,47 @@ impl<'r> RouterTrie<'r> {
     }
 
     /// Expand handlers for each http method as a match statement.
+    ///
+    /// Unless disabled with `#![no_auto_head]`, `HEAD` requests fall back to
+    /// the `GET` routes. The body of the response is stripped by the
+    /// server.
     fn expand_handlers(&self) -> TokenStream {
         let catch_all_expanded = self.expand_catch_all();
 
+        let mut head_arms = self.expand_nodes("", self.head.children());
+        if self.auto_head {
+            head_arms.append_all(self.expand_nodes("", self.get.children()));
+        }
+
         let arms = [
             (
                 hquote! { ::submillisecond::http::Method::GET },
-                self.get.children(),
+                self.expand_nodes("", self.get.children()),
             ),
             (
                 hquote! { ::submillisecond::http::Method::POST },
-                self.post.children(),
+                self.expand_nodes("", self.post.children()),
             ),
             (
                 hquote! { ::submillisecond::http::Method::PUT },
-                self.put.children(),
+                self.expand_nodes("", self.put.children()),
             ),
             (
                 hquote! { ::submillisecond::http::Method::DELETE },
-                self.delete.children(),
-            ),
-            (
-                hquote! { ::submillisecond::http::Method::HEAD },
-                self.head.children(),
+                self.expand_nodes("", self.delete.children()),
             ),
+            (hquote! { ::submillisecond::http::Method::HEAD }, head_arms),
             (
                 hquote! { ::submillisecond::http::Method::OPTIONS },
-                self.options.children(),
+                self.expand_nodes("", self.options.children()),
             ),
             (
                 hquote! { ::submillisecond::http::Method::PATCH },
-                self.patch.children(),
+                self.expand_nodes("", self.patch.children()),
             ),
         ]
         .into_iter()
-        .filter_map(|(method, children)| {
-            let arms = self.expand_nodes("", children);
+        .filter_map(|(method, arms)| {
             if arms.is_empty() {
                 return None;
             }
@@ -149,17 +171,115 @@ impl<'r> RouterTrie<'r> {
             Some(hquote! {
                 #method => {
                     #arms
-                    #catch_all_expanded
                 }
             })
         });
 
+        let method_not_allowed_expanded = self.expand_method_not_allowed();
+
         hquote! {
             match *req.method() {
                 #( #arms )*
-                _ => {
-                    #catch_all_expanded
+                _ => {}
+            }
+            #method_not_allowed_expanded
+            #catch_all_expanded
+        }
+    }
+
+    /// Expand the lookup of the methods allowed for the path, from the paths of
+    /// the method routes.
+    ///
+    /// Requests to these paths with a method that has no route for the path are
+    /// answered with `405 Method Not Allowed`, or with the allowed methods for
+    /// `OPTIONS` requests. Requests with a method that has a route, which
+    /// didn't match because of a guard, fall through to the catch-all.
+    fn expand_method_not_allowed(&self) -> TokenStream {
+        if self.allowed.is_empty() {
+            return hquote! {};
+        }
+
+        let checks = self.allowed.iter().map(|(path, methods)| {
+            let matches = Self::expand_path_match(path);
+            hquote! {
+                if #matches {
+                    allowed |= #methods;
+                }
+            }
+        });
+
+        let methods = ALLOWED_METHODS;
+        let options = method_bit("OPTIONS");
+        hquote! {
+            let allowed = {
+                let rest = req.reader.read_to_end();
+                let mut allowed = 0_u8;
+                #( #checks )*
+                allowed
+            };
+            let methods = [ #( #methods ),* ];
+            let method = methods
+                .iter()
+                .position(|method| *method == req.method().as_str())
+                .map_or(0, |i| 1_u8 << i);
+            if allowed != 0 && allowed & method == 0 {
+                // `OPTIONS` is allowed for every path with a route
+                let allowed = allowed | #options;
+                let allow = methods
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| allowed & (1 << i) != 0)
+                    .map(|(_, method)| *method)
+                    .collect::<::std::vec::Vec<_>>()
+                    .join(", ");
+                if *req.method() == ::submillisecond::http::Method::OPTIONS {
+                    return ::submillisecond::defaults::options(&allow);
                 }
+                return ::submillisecond::defaults::err_405(&allow);
+            }
+        }
+    }
+
+    /// Expand a check of the remainder of the uri, `rest`, matching a route
+    /// `path`, without reading it or pushing params.
+    fn expand_path_match(path: &str) -> TokenStream {
+        let strip_prefix = |prefix: &str, inner: TokenStream| {
+            if prefix.is_empty() {
+                return inner;
+            }
+            hquote! {
+                match rest.strip_prefix(#prefix) {
+                    ::std::option::Option::Some(rest) => #inner,
+                    ::std::option::Option::None => false,
+                }
+            }
+        };
+
+        if let Some((prefix, _, constraint, suffix)) = Self::capture_param_parts(path) {
+            let inner = Self::expand_path_match(suffix);
+            let check = match constraint.map(ParamConstraint::parse) {
+                Some(Ok(constraint)) => {
+                    let check = constraint.expand_check();
+                    hquote! { && #check }
+                }
+                // constraints are validated when parsing the route
+                Some(Err(_)) | None => hquote! {},
+            };
+            return strip_prefix(
+                prefix,
+                hquote! {{
+                    let (value, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                    !value.is_empty() #check && #inner
+                }},
+            );
+        }
+
+        match Self::capture_wildcard(path) {
+            Some((prefix, _)) => hquote! { rest.starts_with(#prefix) },
+            None => {
+                let path = path.strip_suffix('/').unwrap_or(path);
+                let path_slash = format!("{path}/");
+                hquote! { (rest == #path || rest == #path_slash) }
             }
         }
     }
@@ -184,14 +304,14 @@ impl<'r> RouterTrie<'r> {
         let full_path = format!("{full_path}{prefix}");
         let child_nodes_expanded = self.expand_nodes(&full_path, children);
 
+        if let Some((prefix, param, constraint, suffix)) = Self::capture_param_parts(prefix) {
+            return self.expand_param_node(&full_path, node, prefix, param, constraint, suffix);
+        }
+
         match value {
             Some(value) => {
-                if let Some((prefix, param, suffix)) = Self::capture_param_parts(prefix) {
-                    return self.expand_param_node(&full_path, node, prefix, param, suffix);
-                }
-
-                if let Some(prefix) = Self::capture_wildcard(prefix) {
-                    return self.expand_wildcard_node(prefix, value);
+                if let Some((prefix, name)) = Self::capture_wildcard(prefix) {
+                    return self.expand_wildcard_node(prefix, name, value);
                 }
 
                 self.expand_static_node(prefix, value, child_nodes_expanded)
@@ -226,7 +346,7 @@ impl<'r> RouterTrie<'r> {
         let ExpandedNodeParts {
             guards_expanded,
             handler_expanded,
-        } = Self::expand_node_parts(value, false, prefix);
+        } = self.expand_node_parts(value, false, prefix);
 
         let dangling_slash_match = if let (NodeType::Handler, "/") = (value.node_type, prefix) {
             if guards_expanded.is_empty() {
@@ -259,14 +379,29 @@ impl<'r> RouterTrie<'r> {
         }
     }
 
-    fn expand_wildcard_node(&self, prefix: &str, value: &TrieValue<'r>) -> TokenStream {
+    /// Expand a wildcard node, capturing the remainder of the path as a param
+    /// if the wildcard is named.
+    fn expand_wildcard_node(
+        &self,
+        prefix: &str,
+        name: Option<&str>,
+        value: &TrieValue<'r>,
+    ) -> TokenStream {
         let ExpandedNodeParts {
             guards_expanded,
             handler_expanded,
-        } = Self::expand_node_parts(value, true, prefix);
+        } = self.expand_node_parts(value, true, prefix);
+
+        let param_expanded = match name {
+            Some(name) => hquote! {
+                req.params.push(#name, req.reader.read_to_end().to_string());
+            },
+            None => hquote! {},
+        };
 
         quote_reader_fallback! {
             if req.reader.read_matching(#prefix) #guards_expanded {
+                #param_expanded
                 #handler_expanded
             }
         }
@@ -279,6 +414,7 @@ impl<'r> RouterTrie<'r> {
         node: &Node<TrieValue<'r>>,
         prefix: &str,
         param: &str,
+        constraint: Option<&str>,
         suffix: &str,
     ) -> TokenStream {
         let mut expanded = hquote! {};
@@ -288,7 +424,7 @@ impl<'r> RouterTrie<'r> {
                     let ExpandedNodeParts {
                         guards_expanded,
                         handler_expanded,
-                    } = Self::expand_node_parts(value, false, prefix);
+                    } = self.expand_node_parts(value, false, prefix);
 
                     expanded.append_all(hquote! {
                         if req.reader.is_dangling_slash() #guards_expanded {
@@ -313,8 +449,8 @@ impl<'r> RouterTrie<'r> {
             _ => {
                 let captures = Self::capture_param_parts(suffix);
                 let conseq_expanded = match captures {
-                    Some((prefix, param, suffix)) => {
-                        self.expand_param_node(full_path, node, prefix, param, suffix)
+                    Some((prefix, param, constraint, suffix)) => {
+                        self.expand_param_node(full_path, node, prefix, param, constraint, suffix)
                     }
                     None => hquote! {},
                 };
@@ -322,11 +458,15 @@ impl<'r> RouterTrie<'r> {
                 if !conseq_expanded.is_empty() {
                     expanded.append_all(conseq_expanded);
                 } else if conseq_expanded.is_empty() && node.is_leaf() {
-                    if let Some(value) = &node.value {
+                    if let (Some(value), Some((suffix, name))) =
+                        (&node.value, Self::capture_wildcard(suffix))
+                    {
+                        expanded.append_all(self.expand_wildcard_node(suffix, name, value));
+                    } else if let Some(value) = &node.value {
                         let ExpandedNodeParts {
                             guards_expanded,
                             handler_expanded,
-                        } = Self::expand_node_parts(value, false, prefix);
+                        } = self.expand_node_parts(value, false, prefix);
 
                         expanded.append_all(quote_reader_fallback! {
                             if req.reader.read_matching(#suffix) #guards_expanded {
@@ -341,7 +481,7 @@ impl<'r> RouterTrie<'r> {
                         let ExpandedNodeParts {
                             guards_expanded,
                             handler_expanded,
-                        } = Self::expand_node_parts(value, false, prefix);
+                        } = self.expand_node_parts(value, false, prefix);
 
                         expanded.append_all(quote_reader_fallback! {
                             if req.reader.read_matching(#suffix) #guards_expanded {
@@ -350,18 +490,35 @@ impl<'r> RouterTrie<'r> {
                             }
                         });
                     } else {
-                        expanded.append_all(recur);
+                        expanded.append_all(quote_reader_fallback! {
+                            if req.reader.read_matching(#suffix) {
+                                #recur
+                            }
+                        });
                     }
                 };
             }
         }
 
-        // now we insert parsing of param
+        // now we insert parsing of param, which falls through to other routes if it
+        // doesn't match the constraint
+        let param_expanded = match constraint.map(ParamConstraint::parse) {
+            Some(Ok(constraint)) => {
+                let check = constraint.expand_check();
+                hquote! { req.reader.read_param().filter(|value| #check) }
+            }
+            // constraints are validated when parsing the route
+            Some(Err(_)) | None => hquote! { req.reader.read_param() },
+        };
         expanded = quote_reader_fallback! {
-            let param = req.reader.read_param();
+            let param = #param_expanded;
             if let Some(value) = param {
+                let params_len = req.params.len();
                 req.params.push(#param, value.to_string());
                 #expanded
+                // remove the param if the route didn't match
+                #[allow(unreachable_code)]
+                { req.params.truncate(params_len); }
             }
         };
 
@@ -379,6 +536,7 @@ impl<'r> RouterTrie<'r> {
 
     /// Expand a node guards and handler.
     fn expand_node_parts(
+        &self,
         TrieValue {
             guards: guard,
             handler,
@@ -394,9 +552,7 @@ impl<'r> RouterTrie<'r> {
             .fold(hquote! {}, |acc, guard| hquote! { #acc && #guard });
 
         let handler_expanded = match node_type {
-            NodeType::Handler => {
-                Self::expand_handler(method, handler, middleware, wildcard, prefix)
-            }
+            NodeType::Handler => self.expand_handler(method, handler, middleware, wildcard, prefix),
             NodeType::Subrouter => Self::expand_subrouter(handler, middleware),
         };
 
@@ -408,6 +564,7 @@ impl<'r> RouterTrie<'r> {
 
     /// Expand a handler.
     fn expand_handler(
+        &self,
         method: &'r Option<Method>,
         handler: &ItemHandler,
         middleware: &'r Option<ItemWithMiddleware>,
@@ -532,6 +689,7 @@ impl<'r> RouterTrie<'r> {
 
     /// Insert a handler with a prefixed http method.
     fn insert_handler(&mut self, method: Method, key: String, value: TrieValue<'r>) {
+        self.allow(method, &key);
         match method {
             Method::Get(_) => self.get.insert(key, value),
             Method::Post(_) => self.post.insert(key, value),
@@ -543,6 +701,21 @@ impl<'r> RouterTrie<'r> {
         };
     }
 
+    /// Add `method` to the methods allowed for `path`, and `HEAD` for `GET`
+    /// routes unless automatic `HEAD` handling is disabled.
+    fn allow(&mut self, method: Method, path: &str) {
+        let name = method.to_string();
+        let mut methods = method_bit(&name);
+        if name == "GET" && self.auto_head {
+            methods |= method_bit("HEAD");
+        }
+
+        match self.allowed.iter_mut().find(|(allowed, _)| allowed == path) {
+            Some((_, allowed)) => *allowed |= methods,
+            None => self.allowed.push((path.to_string(), methods)),
+        }
+    }
+
     /// Recursively collect handlers and subrouters.
     fn collect_tries(
         &mut self,
@@ -581,33 +754,108 @@ impl<'r> RouterTrie<'r> {
                 },
             };
 
-            if let Some(method) = *method {
-                self.insert_handler(method, new_path, value);
-            } else {
-                self.insert_subrouter(new_path, value);
+            for new_path in Self::expand_optional_segments(&new_path) {
+                if let Some(method) = *method {
+                    self.insert_handler(method, new_path, value.clone());
+                } else {
+                    self.insert_subrouter(new_path, value.clone());
+                }
+            }
+        }
+    }
+
+    /// Expand optional segments into every path they can match, with the
+    /// segments present first.
+    /// Eg. `/posts/:id/:format?` expands to `/posts/:id/:format` and
+    /// `/posts/:id`
+    pub fn expand_optional_segments(path: &str) -> Vec<String> {
+        let mut paths = vec![String::new()];
+        for (i, segment) in path.split('/').enumerate() {
+            let separator = if i == 0 { "" } else { "/" };
+            match segment
+                .strip_suffix('?')
+                .filter(|segment| segment.starts_with(':'))
+            {
+                Some(segment) => {
+                    paths = paths
+                        .into_iter()
+                        .flat_map(|path| [format!("{path}{separator}{segment}"), path])
+                        .collect();
+                }
+                None => {
+                    for path in &mut paths {
+                        path.push_str(separator);
+                        path.push_str(segment);
+                    }
+                }
             }
         }
+
+        paths
+            .into_iter()
+            .map(|path| {
+                if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                }
+            })
+            .collect()
     }
 
     /// Capture param parts from url.
-    /// Eg. `/:foo`
-    fn capture_param_parts(s: &str) -> Option<(&str, &str, &str)> {
+    /// Eg. `/:foo` or `/:foo<u64>`
+    pub fn capture_param_parts(s: &str) -> Option<(&str, &str, Option<&str>, &str)> {
         RE.captures(s).map(|captures| {
             (
-                captures.get(1).unwrap().as_str(),
-                captures.get(2).unwrap().as_str(),
-                captures.get(3).unwrap().as_str(),
+                captures.name("lit_prefix").unwrap().as_str(),
+                captures.name("param").unwrap().as_str(),
+                captures
+                    .name("constraint")
+                    .map(|constraint| constraint.as_str()),
+                captures.name("lit_suffix").unwrap().as_str(),
             )
         })
     }
 
-    /// Capture param parts from url.
-    /// Eg. `/*`
-    fn capture_wildcard(s: &str) -> Option<&str> {
-        if s.contains('*') {
-            s.split('*').next()
-        } else {
-            None
-        }
+    /// Capture wildcard parts from url, with the name of the wildcard if any.
+    /// Eg. `/*` or `/*path`
+    fn capture_wildcard(s: &str) -> Option<(&str, Option<&str>)> {
+        s.split_once('*')
+            .map(|(prefix, name)| (prefix, Some(name).filter(|name| !name.is_empty())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RouterTrie;
+
+    #[test]
+    fn expand_optional_segments() {
+        assert_eq!(
+            RouterTrie::expand_optional_segments("/posts/:id/:format?"),
+            vec!["/posts/:id/:format", "/posts/:id"]
+        );
+        assert_eq!(
+            RouterTrie::expand_optional_segments("/:lang?/:page<[a-z]+>?"),
+            vec!["/:lang/:page<[a-z]+>", "/:lang", "/:page<[a-z]+>", "/"]
+        );
+        assert_eq!(
+            RouterTrie::expand_optional_segments("/posts/:id"),
+            vec!["/posts/:id"]
+        );
+    }
+
+    #[test]
+    fn capture_wildcard() {
+        assert_eq!(
+            RouterTrie::capture_wildcard("/any-*"),
+            Some(("/any-", None))
+        );
+        assert_eq!(
+            RouterTrie::capture_wildcard("/files/*path"),
+            Some(("/files/", Some("path")))
+        );
+        assert_eq!(RouterTrie::capture_wildcard("/files"), None);
     }
 }
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use std::mem;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Trie<V> {
     node: TrieNode<V>,
 }
@@ -26,8 +26,17 @@ where
         })
     }
 
+    /// Insert a value, after the values already inserted with the same key.
     pub fn insert(&mut self, key: String, new_value: V) {
-        self.node.insert(key, new_value)
+        if let Some(new_value) = self.node.insert(key.clone(), new_value) {
+            // the root has the same key, so it's kept as a sibling of the new node
+            let node = mem::replace(&mut self.node, TrieNode::Empty);
+            self.node = TrieNode::Node {
+                value: None,
+                prefix: String::new(),
+                children: vec![node, TrieNode::create_terminal(key, new_value)],
+            };
+        }
     }
 }
 
@@ -53,7 +62,9 @@ where
         }
     }
 
-    pub fn insert(&mut self, key: String, new_value: V) {
+    /// Insert a value, or return it if the node already has a value for the
+    /// key, so it can be inserted as a sibling of the node.
+    pub fn insert(&mut self, key: String, new_value: V) -> Option<V> {
         let key_len = key.len();
         match self {
             TrieNode::Node {
@@ -61,30 +72,25 @@ where
                 ref mut children,
                 ref mut value,
             } => {
-                let mut last_match = key_len;
-                for (idx, b) in key.chars().enumerate() {
-                    // if node prefix ended, try to delegate to a child
-                    if idx >= prefix.len() {
-                        return Self::delegate_to_child(
-                            key[idx..].to_string(),
-                            new_value,
-                            children,
-                        );
-                    }
-                    // if matches current node, delegate to a child
-                    if b == prefix.chars().nth(idx).unwrap() {
-                        continue;
-                    } else {
-                        // they are not the same, need to split at longest common prefix
-                        last_match = idx;
-                        break;
-                    }
-                }
-                // inserting the same key
+                let common = prefix
+                    .bytes()
+                    .zip(key.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let last_match = Self::param_boundary(prefix, &key, common);
+                // if node prefix ended, try to delegate to a child
                 if last_match == prefix.len() {
-                    return;
+                    // inserting the same key
+                    if last_match == key_len {
+                        if value.is_some() {
+                            return Some(new_value);
+                        }
+                        *value = Some(new_value);
+                        return None;
+                    }
+                    Self::delegate_to_child(key[last_match..].to_string(), new_value, children);
+                    return None;
                 }
-                // in this case, key_len will ALWAYS be shorter than prefix.len()
                 // prefix has left-over data, need to split the prefix
                 let (new_prefix, suffix) = prefix.split_at(last_match);
                 // create new node that carries data from current node
@@ -121,24 +127,63 @@ where
                 *self = TrieNode::create_terminal(key, new_value);
             }
         }
+        None
+    }
+
+    /// Moves the end of the common prefix back to the start of a param, so a
+    /// param (and its constraint) is never split between nodes.
+    fn param_boundary(prefix: &str, key: &str, mut common: usize) -> usize {
+        while !key.is_char_boundary(common) || !prefix.is_char_boundary(common) {
+            common -= 1;
+        }
+        let continues_segment = |s: &str| s[common..].chars().next().map_or(false, |c| c != '/');
+        if !continues_segment(prefix) && !continues_segment(key) {
+            return common;
+        }
+        Self::param_start(key, common).unwrap_or(common)
+    }
+
+    /// Finds the start of the last param in the segment before `end`, skipping
+    /// the colons in the constraints of params.
+    fn param_start(key: &str, end: usize) -> Option<usize> {
+        let segment_start = key[..end].rfind('/').map_or(0, |slash| slash + 1);
+        let mut start = None;
+        let mut in_constraint = false;
+        for (i, c) in key[segment_start..end].char_indices() {
+            match c {
+                ':' if !in_constraint => start = Some(segment_start + i),
+                '<' if start.is_some() => in_constraint = true,
+                '>' => in_constraint = false,
+                _ => {}
+            }
+        }
+        start
     }
 
     fn delegate_to_child(key: String, new_value: V, children: &mut Vec<TrieNode<V>>) {
-        let next = key.chars().next();
         // if we find any existing match for the next one we pass it on
         let child = children.iter_mut().find(|c| {
             if let TrieNode::Node { prefix, .. } = c {
-                // if first character matches we found the right child
-                return prefix.chars().next() == next;
+                // if they share a prefix, without splitting a param, we found the right
+                // child
+                let common = prefix
+                    .bytes()
+                    .zip(key.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                return Self::param_boundary(prefix, &key, common) > 0;
             }
             false
         });
-        if let Some(child) = child {
-            child.insert(key, new_value);
-        } else {
-            // create a new terminal child
-            children.push(TrieNode::create_terminal(key, new_value));
-        }
+        let new_value = match child {
+            Some(child) => match child.insert(key.clone(), new_value) {
+                Some(new_value) => new_value,
+                None => return,
+            },
+            None => new_value,
+        };
+        // create a new terminal child, after any child with the same key
+        children.push(TrieNode::create_terminal(key, new_value));
     }
 
     pub fn children(&self) -> Children<V> {
@@ -227,4 +272,66 @@ mod tests {
         let prefixes: Vec<_> = trie.children().map(|c| c.prefix).collect();
         assert_eq!(prefixes, vec!["/"])
     }
+
+    #[test]
+    fn trie_params_are_not_split() {
+        let mut trie = Trie::default();
+        trie.insert("/users/:id<u64>".to_string(), "id");
+        trie.insert("/users/:id<uuid::Uuid>".to_string(), "uuid");
+        trie.insert("/users/:slug".to_string(), "slug");
+        trie.insert("/users/new".to_string(), "new");
+
+        let users = trie.children().next().unwrap();
+        assert_eq!(users.prefix, "/users/");
+        let prefixes: Vec<_> = users.children().map(|c| c.prefix).collect();
+        assert_eq!(
+            prefixes,
+            vec![":id<u64>", ":id<uuid::Uuid>", ":slug", "new"]
+        );
+    }
+
+    #[test]
+    fn trie_split_node_keeps_value() {
+        let mut trie = Trie::default();
+        trie.insert("/a/b".to_string(), "b");
+        trie.insert("/a/c".to_string(), "c");
+        trie.insert("/a/".to_string(), "a");
+
+        let a = trie.children().next().unwrap();
+        assert_eq!(a.prefix, "/a/");
+        assert_eq!(a.value, Some("a"));
+    }
+
+    #[test]
+    fn trie_same_keys_are_kept_in_order() {
+        let mut trie = Trie::default();
+        trie.insert("/a".to_string(), "first");
+        trie.insert("/a".to_string(), "second");
+
+        let root = trie.children().next().unwrap();
+        let nodes: Vec<_> = root.children().map(|c| (c.prefix, c.value)).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("/a".to_string(), Some("first")),
+                ("/a".to_string(), Some("second"))
+            ]
+        );
+
+        let mut trie = Trie::default();
+        trie.insert("/a/b".to_string(), "b");
+        trie.insert("/a/c".to_string(), "first");
+        trie.insert("/a/c".to_string(), "second");
+
+        let a = trie.children().next().unwrap();
+        let nodes: Vec<_> = a.children().map(|c| (c.prefix, c.value)).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("b".to_string(), Some("b")),
+                ("c".to_string(), Some("first")),
+                ("c".to_string(), Some("second"))
+            ]
+        );
+    }
 }
@@ -0,0 +1,148 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::{router, Application, ConnectionLimitPolicy, ServerConfig};
+
+fn limited_server((port, config): (u16, ServerConfig), _: Mailbox<()>) {
+    fn slow() -> &'static str {
+        sleep(Duration::from_millis(100));
+        "Finished"
+    }
+
+    Application::new(router! {
+        GET "/" => slow
+    })
+    .with_config(config)
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+fn send_request(stream: &mut TcpStream) -> String {
+    let request = "\
+        GET / HTTP/1.1\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+const FINISHED: &str = "\
+    HTTP/1.1 200 OK\r\n\
+    content-type: text/plain; charset=utf-8\r\n\
+    connection: close\r\n\
+    content-length: 8\r\n\
+    \r\n\
+    Finished";
+
+#[test]
+fn reject_connections_over_limit() {
+    let config = ServerConfig {
+        max_connections: Some(1),
+        connection_limit_policy: ConnectionLimitPolicy::Reject {
+            retry_after: Duration::from_millis(1500),
+        },
+        date_header: false,
+        ..Default::default()
+    };
+    Process::spawn_link((9050, config), limited_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    Process::spawn_link((), |_, _: Mailbox<()>| {
+        let mut stream = TcpStream::connect("localhost:9050").unwrap();
+        assert_eq!(send_request(&mut stream), FINISHED);
+    });
+    sleep(Duration::from_millis(20));
+    let mut stream = TcpStream::connect("localhost:9050").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 503 Service Unavailable\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        retry-after: 2\r\n\
+        connection: close\r\n\
+        content-length: 19\r\n\
+        \r\n\
+        Service Unavailable"
+    );
+}
+
+#[test]
+fn hold_connections_over_ip_limit() {
+    let config = ServerConfig {
+        max_connections_per_ip: Some(1),
+        date_header: false,
+        ..Default::default()
+    };
+    Process::spawn_link((9051, config), limited_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    Process::spawn_link((), |_, _: Mailbox<()>| {
+        let mut stream = TcpStream::connect("localhost:9051").unwrap();
+        assert_eq!(send_request(&mut stream), FINISHED);
+    });
+    sleep(Duration::from_millis(20));
+    // The second connection is handled once the first one is closed
+    let mut stream = TcpStream::connect("localhost:9051").unwrap();
+    assert_eq!(send_request(&mut stream), FINISHED);
+}
+
+#[test]
+fn expire_held_connections() {
+    let config = ServerConfig {
+        max_connections_per_ip: Some(1),
+        hold_timeout: Duration::from_millis(20),
+        date_header: false,
+        ..Default::default()
+    };
+    Process::spawn_link((9052, config), limited_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    Process::spawn_link((), |_, _: Mailbox<()>| {
+        let mut stream = TcpStream::connect("localhost:9052").unwrap();
+        assert_eq!(send_request(&mut stream), FINISHED);
+    });
+    sleep(Duration::from_millis(20));
+    // The first connection is still handled when the hold timeout passes
+    let mut stream = TcpStream::connect("localhost:9052").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 503 Service Unavailable\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        retry-after: 1\r\n\
+        connection: close\r\n\
+        content-length: 19\r\n\
+        \r\n\
+        Service Unavailable"
+    );
+}
+
+#[test]
+fn reject_held_connections_over_limit() {
+    let config = ServerConfig {
+        max_connections_per_ip: Some(1),
+        max_held_connections: 0,
+        date_header: false,
+        ..Default::default()
+    };
+    Process::spawn_link((9053, config), limited_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    Process::spawn_link((), |_, _: Mailbox<()>| {
+        let mut stream = TcpStream::connect("localhost:9053").unwrap();
+        assert_eq!(send_request(&mut stream), FINISHED);
+    });
+    sleep(Duration::from_millis(20));
+    let mut stream = TcpStream::connect("localhost:9053").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("retry-after: 30\r\n"));
+}
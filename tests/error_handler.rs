@@ -0,0 +1,94 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::http::header;
+use submillisecond::response::{IntoResponse, Response};
+use submillisecond::{router, Application, Failure, ServerConfig};
+
+fn error_handler_server(port: u16, _: Mailbox<()>) {
+    fn panics() -> &'static str {
+        panic!("handler failed")
+    }
+
+    fn error_handler(failure: Failure) -> Response {
+        let body = format!(
+            r#"{{"status":{},"kind":"{:?}","detail":"{}","method":"{}","path":"{}"}}"#,
+            failure.status().as_u16(),
+            failure.kind(),
+            failure.message().unwrap_or_default(),
+            failure.method().unwrap_or_default(),
+            failure.path().unwrap_or_default(),
+        );
+        (
+            failure.status(),
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            body,
+        )
+            .into_response()
+    }
+
+    Application::new(router! {
+        GET "/panic" => panics
+    })
+    .with_error_handler(error_handler)
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+fn request(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(format!("localhost:{port}")).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn error_handler_renders_panic() {
+    Process::spawn_link(9070, error_handler_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let response = request(
+        9070,
+        "\
+        GET /panic?id=1 HTTP/1.1\r\n\
+        \r\n",
+    );
+    assert_eq!(
+        response,
+        "HTTP/1.1 500 Internal Server Error\r\n\
+        content-type: application/problem+json\r\n\
+        content-length: 86\r\n\
+        \r\n\
+        {\"status\":500,\"kind\":\"Panic\",\"detail\":\"handler failed\",\"method\":\"GET\",\"path\":\"/panic\"}"
+    );
+}
+
+#[test]
+fn error_handler_renders_invalid_request() {
+    Process::spawn_link(9071, error_handler_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let response = request(
+        9071,
+        "\
+        POST / HTTP/1.1\r\n\
+        Content-length: five\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(
+        response,
+        "HTTP/1.1 400 Bad Request\r\n\
+        content-type: application/problem+json\r\n\
+        content-length: 94\r\n\
+        \r\n\
+        {\"status\":400,\"kind\":\"InvalidRequest\",\"detail\":\"invalid content-length\",\"method\":\"\",\"path\":\"\"}"
+    );
+}
@@ -0,0 +1,341 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::response::{IntoResponse, Response, StreamBody};
+use submillisecond::sse::{Sse, SseConnection, SseResponse};
+use submillisecond::{router, Application, Failure, ShutdownHandle};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const SETTINGS: u8 = 0x4;
+const GOAWAY: u8 = 0x7;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+fn http2_server(port: u16, _: Mailbox<()>) {
+    fn hello() -> &'static str {
+        "Hello world!"
+    }
+
+    fn echo(body: Vec<u8>) -> Vec<u8> {
+        body
+    }
+
+    fn stream() -> StreamBody {
+        StreamBody::new(["Hello ", "", "streaming"])
+    }
+
+    fn events(sse: Sse) -> SseResponse {
+        sse.on_upgrade((), |_: SseConnection, ()| {})
+    }
+
+    Application::new(router! {
+        GET "/" => hello
+        POST "/echo" => echo
+        GET "/stream" => stream
+        GET "/events" => events
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+fn http2_shutdown_server((port, parent): (u16, Process<ShutdownHandle>), _: Mailbox<()>) {
+    fn hello() -> &'static str {
+        "Hello world!"
+    }
+
+    let shutdown = ShutdownHandle::new();
+    parent.send(shutdown);
+    Application::new(router! {
+        GET "/" => hello
+    })
+    .with_shutdown(shutdown)
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+fn http2_error_handler_server(port: u16, _: Mailbox<()>) {
+    fn panics() -> &'static str {
+        panic!("handler failed")
+    }
+
+    fn error_handler(failure: Failure) -> Response {
+        let body = format!(
+            "{} {}",
+            failure.status().as_u16(),
+            failure.message().unwrap_or("-")
+        );
+        (failure.status(), body).into_response()
+    }
+
+    Application::new(router! {
+        GET "/panic" => panics
+    })
+    .with_error_handler(error_handler)
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+fn write_frame(stream: &mut TcpStream, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+    let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend(stream_id.to_be_bytes());
+    frame.extend(payload);
+    stream.write_all(&frame).unwrap();
+}
+
+fn read_frame(stream: &mut TcpStream) -> Frame {
+    let mut header = [0u8; 9];
+    stream.read_exact(&mut header).unwrap();
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).unwrap();
+    Frame {
+        kind: header[3],
+        flags: header[4],
+        stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]),
+        payload,
+    }
+}
+
+/// Reads frames until the stream ends, returning the response headers and
+/// body.
+fn read_response(stream: &mut TcpStream, stream_id: u32) -> (Vec<(String, String)>, Vec<u8>) {
+    let mut decoder = hpack::Decoder::new();
+    let mut headers = Vec::new();
+    let mut body = Vec::new();
+    loop {
+        let frame = read_frame(stream);
+        if frame.stream_id != stream_id {
+            continue;
+        }
+        match frame.kind {
+            HEADERS => {
+                assert!(frame.flags & END_HEADERS != 0);
+                headers = decoder
+                    .decode(&frame.payload)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(name, value)| {
+                        (
+                            String::from_utf8(name).unwrap(),
+                            String::from_utf8(value).unwrap(),
+                        )
+                    })
+                    .collect();
+            }
+            DATA => body.extend(frame.payload),
+            _ => {}
+        }
+        if frame.flags & END_STREAM != 0 {
+            return (headers, body);
+        }
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn prior_knowledge() {
+    Process::spawn_link(9020, http2_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9020").unwrap();
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+
+    let settings = read_frame(&mut stream);
+    assert_eq!(settings.kind, SETTINGS);
+    assert_eq!(settings.flags & ACK, 0);
+    let ack = read_frame(&mut stream);
+    assert_eq!(ack.kind, SETTINGS);
+    assert_eq!(ack.flags & ACK, ACK);
+    write_frame(&mut stream, SETTINGS, ACK, 0, &[]);
+
+    let mut encoder = hpack::Encoder::new();
+    let block = encoder.encode(vec![
+        (&b":method"[..], &b"GET"[..]),
+        (b":scheme", b"http"),
+        (b":path", b"/"),
+        (b":authority", b"localhost"),
+    ]);
+    write_frame(&mut stream, HEADERS, END_HEADERS | END_STREAM, 1, &block);
+    let (headers, body) = read_response(&mut stream, 1);
+    assert_eq!(header(&headers, ":status"), Some("200"));
+    assert_eq!(header(&headers, "content-length"), Some("12"));
+    assert_eq!(body, b"Hello world!");
+
+    let block = encoder.encode(vec![
+        (&b":method"[..], &b"POST"[..]),
+        (b":scheme", b"http"),
+        (b":path", b"/echo"),
+        (b":authority", b"localhost"),
+    ]);
+    write_frame(&mut stream, HEADERS, END_HEADERS, 3, &block);
+    write_frame(&mut stream, DATA, 0, 3, b"Hello ");
+    write_frame(&mut stream, DATA, END_STREAM, 3, b"http/2");
+    let (headers, body) = read_response(&mut stream, 3);
+    assert_eq!(header(&headers, ":status"), Some("200"));
+    assert_eq!(body, b"Hello http/2");
+}
+
+#[test]
+fn upgrade_h2c() {
+    Process::spawn_link(9021, http2_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9021").unwrap();
+    let request = "\
+        POST /echo HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Connection: Upgrade, HTTP2-Settings\r\n\
+        Upgrade: h2c\r\n\
+        HTTP2-Settings: \r\n\
+        Content-Length: 5\r\n\
+        \r\n\
+        Hello"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+
+    let switching =
+        b"HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: h2c\r\n\r\n";
+    let mut response = vec![0u8; switching.len()];
+    stream.read_exact(&mut response).unwrap();
+    assert_eq!(response, switching);
+
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+    // The upgraded request is answered on stream 1
+    let (headers, body) = read_response(&mut stream, 1);
+    assert_eq!(header(&headers, ":status"), Some("200"));
+    assert_eq!(body, b"Hello");
+}
+
+#[test]
+fn shutdown_sends_go_away() {
+    let mailbox = unsafe { Mailbox::<ShutdownHandle>::new() };
+    Process::spawn_link((9022, mailbox.this()), http2_shutdown_server);
+    let shutdown = mailbox.receive();
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9022").unwrap();
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+    let settings = read_frame(&mut stream);
+    assert_eq!(settings.kind, SETTINGS);
+    let ack = read_frame(&mut stream);
+    assert_eq!(ack.kind, SETTINGS);
+
+    shutdown.shutdown();
+    let go_away = read_frame(&mut stream);
+    assert_eq!(go_away.kind, GOAWAY);
+    // No streams were started, and the error code is NO_ERROR
+    assert_eq!(go_away.payload, [0, 0, 0, 0, 0, 0, 0, 0]);
+    // Without active streams the connection is closed right away
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+}
+
+#[test]
+fn panicking_stream_uses_error_handler() {
+    Process::spawn_link(9023, http2_error_handler_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9023").unwrap();
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+
+    let mut encoder = hpack::Encoder::new();
+    let block = encoder.encode(vec![
+        (&b":method"[..], &b"GET"[..]),
+        (b":scheme", b"http"),
+        (b":path", b"/panic"),
+        (b":authority", b"localhost"),
+    ]);
+    write_frame(&mut stream, HEADERS, END_HEADERS | END_STREAM, 1, &block);
+    let (headers, body) = read_response(&mut stream, 1);
+    assert_eq!(header(&headers, ":status"), Some("500"));
+    assert_eq!(body, b"500 handler failed");
+}
+
+#[test]
+fn stream_body_is_sent_in_data_frames() {
+    Process::spawn_link(9024, http2_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9024").unwrap();
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+
+    let mut encoder = hpack::Encoder::new();
+    let block = encoder.encode(vec![
+        (&b":method"[..], &b"GET"[..]),
+        (b":scheme", b"http"),
+        (b":path", b"/stream"),
+        (b":authority", b"localhost"),
+    ]);
+    write_frame(&mut stream, HEADERS, END_HEADERS | END_STREAM, 1, &block);
+    let (headers, body) = read_response(&mut stream, 1);
+    assert_eq!(header(&headers, ":status"), Some("200"));
+    assert_eq!(header(&headers, "content-length"), None);
+    assert_eq!(body, b"Hello streaming");
+}
+
+#[test]
+fn event_stream_is_not_implemented() {
+    Process::spawn_link(9025, http2_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9025").unwrap();
+    stream.write_all(PREFACE).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+
+    let mut encoder = hpack::Encoder::new();
+    let block = encoder.encode(vec![
+        (&b":method"[..], &b"GET"[..]),
+        (b":scheme", b"http"),
+        (b":path", b"/events"),
+        (b":authority", b"localhost"),
+    ]);
+    write_frame(&mut stream, HEADERS, END_HEADERS | END_STREAM, 1, &block);
+    let (headers, _) = read_response(&mut stream, 1);
+    assert_eq!(header(&headers, ":status"), Some("501"));
+}
+
+#[test]
+fn preface_split_across_reads() {
+    Process::spawn_link(9026, http2_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9026").unwrap();
+    // The first read ends before the part of the preface telling it apart from
+    // an HTTP/1.1 request
+    stream.write_all(&PREFACE[..12]).unwrap();
+    stream.flush().unwrap();
+    sleep(Duration::from_millis(10));
+    stream.write_all(&PREFACE[12..]).unwrap();
+    write_frame(&mut stream, SETTINGS, 0, 0, &[]);
+
+    let settings = read_frame(&mut stream);
+    assert_eq!(settings.kind, SETTINGS);
+    assert_eq!(settings.flags & ACK, 0);
+}
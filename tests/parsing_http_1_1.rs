@@ -4,7 +4,10 @@ use std::time::Duration;
 
 use lunatic::net::TcpStream;
 use lunatic::{sleep, test, Mailbox, Process};
-use submillisecond::{router, Application, Body};
+use submillisecond::extract::BodyStream;
+use submillisecond::http::header;
+use submillisecond::response::StreamBody;
+use submillisecond::{router, Application, Body, Guard, RequestContext, ServerConfig};
 
 fn hell_world_server(port: u16, _: Mailbox<()>) {
     fn hello_world_handler() -> &'static str {
@@ -14,6 +17,10 @@ fn hell_world_server(port: u16, _: Mailbox<()>) {
     Application::new(router! {
         GET "/" => hello_world_handler
     })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
     .serve(format!("localhost:{port}"))
     .unwrap();
 }
@@ -39,6 +46,30 @@ fn empty_line_prefix_is_valid() {
     );
 }
 
+#[test]
+fn head_request() {
+    Process::spawn_link(8932, hell_world_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8932").unwrap();
+    let request = "\
+        HEAD / HTTP/1.1\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 12\r\n\
+        connection: close\r\n\
+        \r\n"
+    );
+}
+
 #[test]
 #[ignore]
 fn pipeline_requests() {
@@ -144,6 +175,10 @@ fn panic_server(port: u16, _: Mailbox<()>) {
     Application::new(router! {
         GET "/" => panic_handler
     })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
     .serve(format!("localhost:{port}"))
     .unwrap();
 }
@@ -177,6 +212,10 @@ fn post_echo_server(port: u16, _: Mailbox<()>) {
     Application::new(router! {
         POST "/" => hello_world_handler
     })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
     .serve(format!("localhost:{port}"))
     .unwrap();
 }
@@ -206,3 +245,703 @@ fn post_request_keep_alive() {
         Hello"
     );
 }
+
+#[test]
+fn post_request_chunked() {
+    Process::spawn_link(8906, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8906").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5;name=value\r\n\
+        Hello\r\n\
+        7\r\n\
+        , world\r\n\
+        0\r\n\
+        Expires: never\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        content-length: 12\r\n\
+        \r\n\
+        Hello, world"
+    );
+}
+
+#[test]
+fn pipeline_requests_chunked() {
+    Process::spawn_link(8907, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8907").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        \r\n\
+        POST / HTTP/1.1\r\n\
+        Content-length: 5\r\n\
+        \r\n\
+        World"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    // First response
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        content-length: 5\r\n\
+        \r\n\
+        Hello"
+    );
+    // Second response
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        content-length: 5\r\n\
+        \r\n\
+        World"
+    );
+}
+
+#[test]
+fn invalid_content_length() {
+    Process::spawn_link(8908, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8908").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Content-length: five\r\n\
+        \r\n\
+        Hello"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 400 Bad Request\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 11\r\n\
+        \r\n\
+        Bad Request"
+    );
+}
+
+#[test]
+fn content_length_too_large() {
+    Process::spawn_link(8909, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8909").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Content-length: 1000000000\r\n\
+        \r\n\
+        Hello"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 413 Payload Too Large\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 17\r\n\
+        \r\n\
+        Payload Too Large"
+    );
+}
+
+#[test]
+fn too_many_headers() {
+    Process::spawn_link(8910, hell_world_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8910").unwrap();
+    let mut request = String::from("GET / HTTP/1.1\r\n");
+    for i in 0..200 {
+        request.push_str(&format!("X-Header-{i}: value\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 431 Request Header Fields Too Large\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 31\r\n\
+        \r\n\
+        Request Header Fields Too Large"
+    );
+}
+
+fn limited_post_echo_server(port: u16, _: Mailbox<()>) {
+    fn hello_world_handler(data: Body) -> Vec<u8> {
+        data.as_slice().into()
+    }
+
+    Application::new(router! {
+        POST "/" => hello_world_handler
+    })
+    .with_config(ServerConfig {
+        max_body_size: 4,
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn configured_max_body_size() {
+    Process::spawn_link(8911, limited_post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8911").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        3\r\n\
+        Hel\r\n\
+        2\r\n\
+        lo\r\n\
+        0\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 413 Payload Too Large\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 17\r\n\
+        \r\n\
+        Payload Too Large"
+    );
+}
+
+fn short_keep_alive_server(port: u16, _: Mailbox<()>) {
+    fn hello_world_handler() -> &'static str {
+        "Hello world!"
+    }
+
+    Application::new(router! {
+        GET "/" => hello_world_handler
+    })
+    .with_config(ServerConfig {
+        keep_alive_timeout: Duration::from_millis(100),
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn idle_keep_alive_closes_quietly() {
+    Process::spawn_link(8912, short_keep_alive_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8912").unwrap();
+    let request = "GET / HTTP/1.1\r\n\r\n".as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 12\r\n\
+        \r\n\
+        Hello world!"
+    );
+    // Connection is closed without sending a response
+    sleep(Duration::from_millis(200));
+    let n = stream.read(&mut response).unwrap();
+    assert_eq!(n, 0);
+}
+
+fn short_header_read_server(port: u16, _: Mailbox<()>) {
+    fn hello_world_handler() -> &'static str {
+        "Hello world!"
+    }
+
+    Application::new(router! {
+        GET "/" => hello_world_handler
+    })
+    .with_config(ServerConfig {
+        header_read_timeout: Duration::from_millis(200),
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn slow_request_head_times_out() {
+    Process::spawn_link(8933, short_header_read_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8933").unwrap();
+    // Each byte arrives before a single read times out, but the whole head
+    // doesn't arrive in time.
+    stream
+        .set_read_timeout(Some(Duration::from_millis(150)))
+        .unwrap();
+    let mut response = [0u8; 256];
+    let mut bytes = "GET / HTTP/1.1\r\n".bytes();
+    let n = loop {
+        stream.write_all(&[bytes.next().unwrap()]).unwrap();
+        if let Ok(n) = stream.read(&mut response) {
+            break n;
+        }
+    };
+    let response = from_utf8(&response[..n]).unwrap();
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+}
+
+#[test]
+fn connection_close() {
+    Process::spawn_link(8913, hell_world_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8913").unwrap();
+    let request = "GET / HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        connection: close\r\n\
+        content-length: 12\r\n\
+        \r\n\
+        Hello world!"
+    );
+    let n = stream.read(&mut response).unwrap();
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn http_1_0_closes_connection() {
+    Process::spawn_link(8914, hell_world_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8914").unwrap();
+    let request = "GET / HTTP/1.0\r\n\r\n".as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.0 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        connection: close\r\n\
+        content-length: 12\r\n\
+        \r\n\
+        Hello world!"
+    );
+    let n = stream.read(&mut response).unwrap();
+    assert_eq!(n, 0);
+}
+
+#[test]
+fn http_1_0_keep_alive() {
+    Process::spawn_link(8915, hell_world_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8915").unwrap();
+    let request = "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n".as_bytes();
+    for _ in 0..2 {
+        stream.write_all(request).unwrap();
+        let mut response = [0u8; 256];
+        let n = stream.read(&mut response).unwrap();
+        let response_str = from_utf8(&response[..n]).unwrap();
+        assert_eq!(
+            response_str,
+            "HTTP/1.0 200 OK\r\n\
+            content-type: text/plain; charset=utf-8\r\n\
+            connection: keep-alive\r\n\
+            content-length: 12\r\n\
+            \r\n\
+            Hello world!"
+        );
+    }
+}
+
+#[test]
+fn expect_continue() {
+    Process::spawn_link(8916, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8916").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Content-length: 5\r\n\
+        Expect: 100-continue\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(response_str, "HTTP/1.1 100 Continue\r\n\r\n");
+    stream.write_all("Hello".as_bytes()).unwrap();
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        content-length: 5\r\n\
+        \r\n\
+        Hello"
+    );
+}
+
+#[test]
+fn expect_continue_too_large() {
+    Process::spawn_link(8917, limited_post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8917").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Content-length: 5\r\n\
+        Expect: 100-continue\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 413 Payload Too Large\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 17\r\n\
+        \r\n\
+        Payload Too Large"
+    );
+}
+
+#[test]
+fn unsupported_expectation() {
+    Process::spawn_link(8918, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8918").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Content-length: 5\r\n\
+        Expect: something-else\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 417 Expectation Failed\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 18\r\n\
+        \r\n\
+        Expectation Failed"
+    );
+}
+
+fn body_stream_server(port: u16, _: Mailbox<()>) {
+    fn count_handler(mut body: BodyStream) -> String {
+        let mut buffer = [0u8; 4];
+        let mut total = 0;
+        loop {
+            match body.read(&mut buffer).unwrap() {
+                0 => break,
+                n => total += n,
+            }
+        }
+        total.to_string()
+    }
+
+    fn ignore_handler() -> &'static str {
+        "Ignored"
+    }
+
+    Application::new(router! {
+        POST "/count" => count_handler
+        POST "/ignore" => ignore_handler
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn stream_request_body() {
+    Process::spawn_link(8919, body_stream_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8919").unwrap();
+    let request = "\
+        POST /count HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hel"
+    .as_bytes();
+    stream.write_all(request).unwrap();
+    sleep(Duration::from_millis(10));
+    stream
+        .write_all("lo\r\n7\r\n, world\r\n0\r\n\r\n".as_bytes())
+        .unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    let response_str = from_utf8(&response[..n]).unwrap();
+    assert_eq!(
+        response_str,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 2\r\n\
+        \r\n\
+        12"
+    );
+}
+
+#[test]
+fn unread_body_is_skipped() {
+    Process::spawn_link(8929, body_stream_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8929").unwrap();
+    let request = "\
+        POST /ignore HTTP/1.1\r\n\
+        Content-length: 5\r\n\
+        \r\n\
+        He"
+    .as_bytes();
+    stream.write_all(request).unwrap();
+    sleep(Duration::from_millis(10));
+    let request = "\
+        lloPOST /count HTTP/1.1\r\n\
+        Content-length: 3\r\n\
+        Connection: close\r\n\
+        \r\n\
+        abc"
+    .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 7\r\n\
+        \r\n\
+        Ignored\
+        HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        connection: close\r\n\
+        content-length: 1\r\n\
+        \r\n\
+        3"
+    );
+}
+
+fn body_guard_server(port: u16, _: Mailbox<()>) {
+    struct BodyIs(&'static [u8]);
+
+    impl Guard for BodyIs {
+        fn check(&self, req: &RequestContext) -> bool {
+            req.body().as_slice() == self.0
+        }
+    }
+
+    fn echo_handler(body: Vec<u8>) -> Vec<u8> {
+        body
+    }
+
+    Application::new(router! {
+        POST "/" if BodyIs(b"Hello") => echo_handler
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn guard_reads_incoming_body() {
+    Process::spawn_link(8934, body_guard_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8934").unwrap();
+    let request = "\
+        POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    sleep(Duration::from_millis(10));
+    stream.write_all(b"3\r\nHel\r").unwrap();
+    sleep(Duration::from_millis(10));
+    stream.write_all(b"\n2\r\nlo\r\n0\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        connection: close\r\n\
+        content-length: 5\r\n\
+        \r\n\
+        Hello"
+    );
+}
+
+fn stream_body_server(port: u16, _: Mailbox<()>) {
+    fn stream_handler() -> StreamBody {
+        StreamBody::new(["Hello", "", ", world"])
+    }
+
+    fn stream_length_handler() -> ([(header::HeaderName, &'static str); 1], StreamBody) {
+        (
+            [(header::CONTENT_LENGTH, "12")],
+            StreamBody::new(["Hello", ", world"]),
+        )
+    }
+
+    Application::new(router! {
+        GET "/" => stream_handler
+        GET "/length" => stream_length_handler
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn stream_response_body() {
+    Process::spawn_link(8930, stream_body_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8930").unwrap();
+    let request = "\
+        GET / HTTP/1.1\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        connection: close\r\n\
+        transfer-encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        7\r\n\
+        , world\r\n\
+        0\r\n\
+        \r\n"
+    );
+}
+
+#[test]
+fn stream_response_body_http_1_0() {
+    Process::spawn_link(8931, stream_body_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8931").unwrap();
+    let request = "\
+        GET / HTTP/1.0\r\n\
+        Connection: keep-alive\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.0 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        connection: close\r\n\
+        \r\n\
+        Hello, world"
+    );
+}
+
+#[test]
+fn stream_response_body_drops_content_length() {
+    Process::spawn_link(8935, stream_body_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:8935").unwrap();
+    let request = "\
+        GET /length HTTP/1.1\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: application/octet-stream\r\n\
+        connection: close\r\n\
+        transfer-encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        7\r\n\
+        , world\r\n\
+        0\r\n\
+        \r\n"
+    );
+}
@@ -0,0 +1,168 @@
+use std::io::{Read, Write};
+use std::str::from_utf8;
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::{router, Application, Body, ServerConfig};
+
+const BAD_REQUEST: &str = "\
+    HTTP/1.1 400 Bad Request\r\n\
+    content-type: text/plain; charset=utf-8\r\n\
+    content-length: 11\r\n\
+    \r\n\
+    Bad Request";
+
+fn post_echo_server(port: u16, _: Mailbox<()>) {
+    fn hello_world_handler(data: Body) -> Vec<u8> {
+        data.as_slice().into()
+    }
+
+    Application::new(router! {
+        POST "/" => hello_world_handler
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+/// Sends the request to a new echo server and returns the response.
+fn send_request(port: u16, request: &str) -> String {
+    Process::spawn_link(port, post_echo_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect(format!("localhost:{port}")).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = [0u8; 256];
+    let n = stream.read(&mut response).unwrap();
+    from_utf8(&response[..n]).unwrap().to_string()
+}
+
+#[test]
+fn duplicate_content_length() {
+    let response = send_request(
+        8920,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: 5\r\n\
+        Content-Length: 5\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn conflicting_content_length() {
+    let response = send_request(
+        8921,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: 5\r\n\
+        Content-Length: 0\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn content_length_list() {
+    let response = send_request(
+        8922,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: 5, 5\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn content_length_with_sign() {
+    let response = send_request(
+        8923,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: +5\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn content_length_and_transfer_encoding() {
+    let response = send_request(
+        8924,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: 4\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        \r\n",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn transfer_encoding_not_chunked_last() {
+    let response = send_request(
+        8925,
+        "POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked, identity\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        \r\n",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn transfer_encoding_chunked_twice() {
+    let response = send_request(
+        8926,
+        "POST / HTTP/1.1\r\n\
+        Transfer-Encoding: chunked\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        \r\n",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn obsolete_line_folding() {
+    let response = send_request(
+        8927,
+        "POST / HTTP/1.1\r\n\
+        Content-Length: 5\r\n\
+        X-Folded: first\r\n \
+        second\r\n\
+        \r\n\
+        Hello",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
+
+#[test]
+fn obsolete_line_folding_transfer_encoding() {
+    let response = send_request(
+        8928,
+        "POST / HTTP/1.1\r\n\
+        Transfer-Encoding:\r\n\tchunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        \r\n",
+    );
+    assert_eq!(response, BAD_REQUEST);
+}
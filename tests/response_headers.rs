@@ -0,0 +1,89 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::http::header::{self, HeaderValue};
+use submillisecond::response::{IntoResponse, Response};
+use submillisecond::{router, Application, ServerConfig};
+
+fn headers_server(port: u16, _: Mailbox<()>) {
+    fn hello() -> &'static str {
+        "Hello"
+    }
+
+    fn custom_headers() -> Response {
+        let mut response = "Hello".into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(5));
+        headers.insert(header::SERVER, HeaderValue::from_static("custom"));
+        headers.insert("x-latin-1", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        response
+    }
+
+    Application::new(router! {
+        GET "/" => hello
+        GET "/custom" => custom_headers
+    })
+    .with_config(ServerConfig {
+        server_header: Some("submillisecond".to_string()),
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+fn request(port: u16, path: &str) -> Vec<u8> {
+    let mut stream = TcpStream::connect(format!("localhost:{port}")).unwrap();
+    let request = format!("GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn date_and_server_headers() {
+    Process::spawn_link(9080, headers_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let response = String::from_utf8(request(9080, "/")).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let mut lines = head.split("\r\n");
+    assert_eq!(lines.next(), Some("HTTP/1.1 200 OK"));
+    let headers: Vec<_> = lines.collect();
+    assert!(headers.contains(&"content-length: 5"));
+    assert!(headers.contains(&"server: submillisecond"));
+    let date = headers
+        .iter()
+        .find_map(|header| header.strip_prefix("date: "))
+        .unwrap();
+    assert!(date.ends_with(" GMT"));
+    assert_eq!(body, "Hello");
+}
+
+#[test]
+fn handler_headers_are_kept() {
+    Process::spawn_link(9081, headers_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let response = request(9081, "/custom");
+    let head_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = &response[..head_end];
+    let headers: Vec<_> = head.split(|byte| *byte == b'\n').collect();
+    let count = |prefix: &[u8]| {
+        headers
+            .iter()
+            .filter(|header| header.starts_with(prefix))
+            .count()
+    };
+    assert_eq!(count(b"content-length: 5\r"), 1);
+    assert_eq!(count(b"content-length"), 1);
+    assert_eq!(count(b"server: custom\r"), 1);
+    assert_eq!(count(b"server"), 1);
+    assert_eq!(count(b"x-latin-1: caf\xe9\r"), 1);
+    assert_eq!(&response[head_end + 4..], b"Hello");
+}
@@ -1,8 +1,10 @@
 use http::Method;
 use lunatic::net::TcpStream;
 use lunatic::test;
+use submillisecond::params::Params;
 use submillisecond::response::Response;
-use submillisecond::{http, router, Body, Handler, RequestContext};
+use submillisecond::routes::RouteInfo;
+use submillisecond::{http, router, Application, Body, Guard, Handler, RequestContext};
 
 macro_rules! build_request {
     ($method: ident, $uri: literal) => {
@@ -51,6 +53,17 @@ macro_rules! assert_404 {
     };
 }
 
+macro_rules! assert_405 {
+    ($res: expr, $allow: expr) => {
+        assert!(
+            $res.status() == http::StatusCode::METHOD_NOT_ALLOWED,
+            "response wasn't 405, but was:\n{:?}",
+            $res
+        );
+        assert_eq!($res.headers()[http::header::ALLOW], $allow);
+    };
+}
+
 fn simple_handler() -> &'static str {
     "OK"
 }
@@ -65,9 +78,9 @@ fn simple_router() {
     let res = handle_request!(router, GET, "/");
     assert_200!(res, b"OK");
 
-    // 404
+    // 405
     let res = handle_request!(router, POST, "/");
-    assert_404!(res);
+    assert_405!(res, "GET, HEAD, OPTIONS");
 }
 
 fn echo_handler(body: Vec<u8>) -> Vec<u8> {
@@ -85,8 +98,9 @@ fn echo_router() {
     assert_200!(res, b"Hello, world!");
 
     // 404
+    // 405
     let res = handle_request!(router, GET, "/echo", b"Hello, world!");
-    assert_404!(res);
+    assert_405!(res, "POST, OPTIONS");
 }
 
 #[test]
@@ -116,8 +130,9 @@ fn nested_router() {
     let res = handle_request!(router, GET, "/a");
     assert_404!(res);
 
+    // 405
     let res = handle_request!(router, POST, "/a/b/c");
-    assert_404!(res);
+    assert_405!(res, "GET, HEAD, OPTIONS");
 }
 
 #[test]
@@ -212,26 +227,32 @@ fn all_methods_router() {
     let res = handle_request!(router, PATCH, "/patch");
     assert_200!(res, b"OK");
 
-    // 404
+    // 405
     let res = handle_request!(router, GET, "/post");
-    assert_404!(res);
+    assert_405!(res, "POST, OPTIONS");
 
     let res = handle_request!(router, POST, "/put");
-    assert_404!(res);
+    assert_405!(res, "PUT, OPTIONS");
 
     let res = handle_request!(router, PUT, "/delete");
-    assert_404!(res);
+    assert_405!(res, "DELETE, OPTIONS");
 
     let res = handle_request!(router, DELETE, "/head");
-    assert_404!(res);
+    assert_405!(res, "HEAD, OPTIONS");
 
     let res = handle_request!(router, HEAD, "/options");
-    assert_404!(res);
+    assert_405!(res, "OPTIONS");
+
+    let res = handle_request!(router, PATCH, "/get");
+    assert_405!(res, "GET, HEAD, OPTIONS");
 
+    // automatic OPTIONS
     let res = handle_request!(router, OPTIONS, "/patch");
-    assert_404!(res);
+    assert_eq!(res.status(), http::StatusCode::NO_CONTENT);
+    assert_eq!(res.headers()[http::header::ALLOW], "PATCH, OPTIONS");
 
-    let res = handle_request!(router, PATCH, "/get");
+    // 404
+    let res = handle_request!(router, GET, "/missing");
     assert_404!(res);
 }
 
@@ -317,3 +338,420 @@ fn router_split() {
     let res = handle_request!(router, GET, "/bba");
     assert_200!(res, b"bba");
 }
+
+fn head_handler() -> &'static str {
+    "HEAD"
+}
+
+#[test]
+fn auto_head_router() {
+    let router = router! {
+        GET "/" => simple_handler
+        HEAD "/head" => head_handler
+        GET "/head" => simple_handler
+        "/nested" => {
+            GET "/get" => simple_handler
+        }
+    };
+
+    // 200 falls back to GET
+    let res = handle_request!(router, HEAD, "/");
+    assert_200!(res, b"OK");
+
+    let res = handle_request!(router, HEAD, "/nested/get");
+    assert_200!(res, b"OK");
+
+    // 200 explicit HEAD route
+    let res = handle_request!(router, HEAD, "/head");
+    assert_200!(res, b"HEAD");
+
+    // 404
+    let res = handle_request!(router, HEAD, "/missing");
+    assert_404!(res);
+}
+
+#[test]
+fn no_auto_head_router() {
+    let router = router! {
+        #![no_auto_head]
+
+        GET "/" => simple_handler
+        "/nested" => {
+            GET "/get" => simple_handler
+        }
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/");
+    assert_200!(res, b"OK");
+
+    // 405
+    let res = handle_request!(router, HEAD, "/");
+    assert_405!(res, "GET, OPTIONS");
+
+    // 405 nested router
+    let res = handle_request!(router, HEAD, "/nested/get");
+    assert_405!(res, "GET, OPTIONS");
+}
+
+fn not_found_handler() -> &'static str {
+    "Not found"
+}
+
+#[test]
+fn method_not_allowed_router() {
+    let router = router! {
+        GET "/users/:id" => simple_handler
+        PUT "/users/:id" => simple_handler
+        OPTIONS "/custom" => head_handler
+        POST "/custom" => simple_handler
+        _ => not_found_handler
+    };
+
+    // 405 takes precedence over the catch-all
+    let res = handle_request!(router, DELETE, "/users/1");
+    assert_405!(res, "GET, HEAD, PUT, OPTIONS");
+
+    // automatic OPTIONS
+    let res = handle_request!(router, OPTIONS, "/users/1");
+    assert_eq!(res.status(), http::StatusCode::NO_CONTENT);
+    assert_eq!(
+        res.headers()[http::header::ALLOW],
+        "GET, HEAD, PUT, OPTIONS"
+    );
+
+    // explicit OPTIONS route
+    let res = handle_request!(router, OPTIONS, "/custom");
+    assert_200!(res, b"HEAD");
+
+    let res = handle_request!(router, GET, "/custom");
+    assert_405!(res, "POST, OPTIONS");
+
+    // catch-all
+    let res = handle_request!(router, DELETE, "/posts/1");
+    assert_200!(res, b"Not found");
+}
+
+fn api_router(req: RequestContext) -> Response {
+    let router = router! {
+        GET "/users" => simple_handler
+    };
+    Handler::handle(&router(), req)
+}
+
+#[test]
+fn method_not_allowed_subrouter() {
+    let router = router! {
+        GET "/api*" => api_router
+        POST "/api/upload" => simple_handler
+        "/admin" => {
+            PUT "/users/:id<u64>" => simple_handler
+        }
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/api/users");
+    assert_200!(res, b"OK");
+
+    // 405
+    let res = handle_request!(router, PUT, "/api/users");
+    assert_405!(res, "GET, HEAD, OPTIONS");
+
+    let res = handle_request!(router, DELETE, "/api/upload");
+    assert_405!(res, "GET, HEAD, POST, OPTIONS");
+
+    let res = handle_request!(router, POST, "/admin/users/1");
+    assert_405!(res, "PUT, OPTIONS");
+
+    // 404
+    let res = handle_request!(router, POST, "/admin/users/new");
+    assert_404!(res);
+}
+
+#[test]
+fn method_not_allowed_guarded_router() {
+    let router = router! {
+        GET "/admin" if DenyAll => simple_handler
+        POST "/admin" => simple_handler
+        OPTIONS "/options" if DenyAll => simple_handler
+        GET "/options" => simple_handler
+    };
+
+    // 404 guard rejected the route with the method
+    let res = handle_request!(router, GET, "/admin");
+    assert_404!(res);
+
+    let res = handle_request!(router, HEAD, "/admin");
+    assert_404!(res);
+
+    let res = handle_request!(router, OPTIONS, "/options");
+    assert_404!(res);
+
+    // 405
+    let res = handle_request!(router, PUT, "/admin");
+    assert_405!(res, "GET, HEAD, POST, OPTIONS");
+}
+
+fn id_handler() -> &'static str {
+    "id"
+}
+
+fn slug_handler() -> &'static str {
+    "slug"
+}
+
+#[test]
+fn constrained_param_router() {
+    let router = router! {
+        GET "/users/:id<u64>" => id_handler
+        GET "/users/:slug<[a-z-]+>" => slug_handler
+        GET "/users/:other" => simple_handler
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/users/42");
+    assert_200!(res, b"id");
+
+    let res = handle_request!(router, GET, "/users/hello-world");
+    assert_200!(res, b"slug");
+
+    let res = handle_request!(router, GET, "/users/-1");
+    assert_200!(res, b"slug");
+
+    let res = handle_request!(router, GET, "/users/Hello");
+    assert_200!(res, b"OK");
+
+    // 404
+    let res = handle_request!(router, GET, "/users/42/posts");
+    assert_404!(res);
+}
+
+fn params_handler(params: Params) -> String {
+    params
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[test]
+fn named_wildcard_router() {
+    let router = router! {
+        GET "/files/*path" => params_handler
+        GET "/users/:id/*rest" => params_handler
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/files/");
+    assert_200!(res, b"path=");
+
+    let res = handle_request!(router, GET, "/files/docs/readme.md");
+    assert_200!(res, b"path=docs/readme.md");
+
+    let res = handle_request!(router, GET, "/users/42/posts/1");
+    assert_200!(res, b"id=42,rest=posts/1");
+
+    // 404
+    let res = handle_request!(router, GET, "/files");
+    assert_404!(res);
+}
+
+#[test]
+fn optional_segment_router() {
+    let router = router! {
+        GET "/posts/:id/:format?" => params_handler
+        GET "/:lang?/about" => params_handler
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/posts/1");
+    assert_200!(res, b"id=1");
+
+    let res = handle_request!(router, GET, "/posts/1/json");
+    assert_200!(res, b"id=1,format=json");
+
+    let res = handle_request!(router, GET, "/about");
+    assert_200!(res, b"");
+
+    let res = handle_request!(router, GET, "/en/about");
+    assert_200!(res, b"lang=en");
+
+    // 404
+    let res = handle_request!(router, GET, "/posts/1/json/extra");
+    assert_404!(res);
+}
+
+#[test]
+fn all_optional_segment_router() {
+    let router = router! {
+        GET "/:lang?/:page?" => params_handler
+    };
+
+    // 200
+    let res = handle_request!(router, GET, "/");
+    assert_200!(res, b"");
+
+    let res = handle_request!(router, GET, "/en");
+    assert_200!(res, b"lang=en");
+
+    let res = handle_request!(router, GET, "/en/about");
+    assert_200!(res, b"lang=en,page=about");
+
+    // 404
+    let res = handle_request!(router, GET, "/en/about/extra");
+    assert_404!(res);
+}
+
+router! {
+    pub mod blog;
+
+    GET "/users/:id<u64>" as user_show => simple_handler
+    "/posts" => {
+        GET "/:slug/:format?" as post_show => params_handler
+    }
+    GET "/files/*path" as file => params_handler
+    GET "/tags/:tag<[a-z-]+>" as tag => params_handler
+}
+
+#[test]
+fn named_routes() {
+    assert_eq!(blog::user_show(42), "/users/42");
+    assert_eq!(
+        blog::post_show("hello world", Some("json")),
+        "/posts/hello%20world/json"
+    );
+    assert_eq!(blog::post_show("a/b", None), "/posts/a%2Fb");
+    assert_eq!(blog::file("docs/read me.md"), "/files/docs/read%20me.md");
+    assert_eq!(blog::tag("web-dev"), "/tags/web-dev");
+
+    let router = blog::router();
+    let res = handle_request!(router, GET, "/posts/hello-world/json");
+    assert_200!(res, b"slug=hello-world,format=json");
+}
+
+#[test]
+#[should_panic(expected = "doesn't match the constraint of the param `tag`")]
+fn named_route_constraint_mismatch() {
+    blog::tag("Web Dev");
+}
+
+struct AllowAll;
+
+impl Guard for AllowAll {
+    fn check(&self, _: &RequestContext) -> bool {
+        true
+    }
+}
+
+mod handlers {
+    pub fn index() -> &'static str {
+        "index"
+    }
+}
+
+fn default_handler<T: Default + ToString>() -> String {
+    T::default().to_string()
+}
+
+#[test]
+fn route_table() {
+    assert_eq!(
+        blog::ROUTES[1],
+        RouteInfo {
+            method: Some("GET"),
+            path: "/posts/:slug/:format?",
+            handler: "params_handler",
+            guards: &[],
+            middleware: &[],
+        }
+    );
+
+    let app = Application::new(router! {
+        with simple_handler;
+
+        GET "/" => simple_handler
+        GET "/index" => handlers::index
+        GET "/default" => default_handler::<u64>
+        "/admin" if AllowAll => {
+            with head_handler;
+
+            GET "/users/:id" with not_found_handler => params_handler
+        }
+    });
+    assert_eq!(
+        app.routes(),
+        &[
+            RouteInfo {
+                method: Some("GET"),
+                path: "/",
+                handler: "simple_handler",
+                guards: &[],
+                middleware: &["simple_handler"],
+            },
+            RouteInfo {
+                method: Some("GET"),
+                path: "/index",
+                handler: "index",
+                guards: &[],
+                middleware: &["simple_handler"],
+            },
+            RouteInfo {
+                method: Some("GET"),
+                path: "/default",
+                handler: "default_handler",
+                guards: &[],
+                middleware: &["simple_handler"],
+            },
+            RouteInfo {
+                method: Some("GET"),
+                path: "/admin/users/:id",
+                handler: "params_handler",
+                guards: &["AllowAll"],
+                middleware: &["simple_handler", "head_handler", "not_found_handler"],
+            },
+        ]
+    );
+}
+
+struct DenyAll;
+
+impl Guard for DenyAll {
+    fn check(&self, _: &RequestContext) -> bool {
+        false
+    }
+}
+
+#[test]
+fn overlapping_routes_router() {
+    let router = router! {
+        GET "/users/new" => head_handler
+        GET "/users/:id" => id_handler
+        GET "/admin" if DenyAll => head_handler
+        GET "/admin" => simple_handler
+        GET "/about" => simple_handler
+        "/api" => {
+            GET "/v1" => slug_handler
+        }
+        "/:lang" if DenyAll => {
+            GET "/" => head_handler
+        }
+    };
+
+    // a static route before a param route
+    let res = handle_request!(router, GET, "/users/new");
+    assert_200!(res, b"HEAD");
+
+    let res = handle_request!(router, GET, "/users/1");
+    assert_200!(res, b"id");
+
+    // a route after the same route with a guard
+    let res = handle_request!(router, GET, "/admin");
+    assert_200!(res, b"OK");
+
+    // a route which isn't a path of a sub router
+    let res = handle_request!(router, GET, "/about");
+    assert_200!(res, b"OK");
+
+    let res = handle_request!(router, GET, "/api/v1");
+    assert_200!(res, b"slug");
+}
@@ -0,0 +1,59 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::{router, Application, ServerConfig, ShutdownHandle};
+
+fn shutdown_server((port, parent): (u16, Process<ShutdownHandle>), _: Mailbox<()>) {
+    fn slow() -> &'static str {
+        sleep(Duration::from_millis(100));
+        "Finished"
+    }
+
+    // The handle shuts down the server in the process that created it
+    let shutdown = ShutdownHandle::new();
+    parent.send(shutdown);
+    Application::new(router! {
+        GET "/" => slow
+    })
+    .with_config(ServerConfig {
+        shutdown_timeout: Duration::from_secs(1),
+        date_header: false,
+        ..Default::default()
+    })
+    .with_shutdown(shutdown)
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn finish_active_requests() {
+    let mailbox = unsafe { Mailbox::<ShutdownHandle>::new() };
+    Process::spawn_link((9040, mailbox.this()), shutdown_server);
+    let shutdown = mailbox.receive();
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9040").unwrap();
+    let request = "\
+        GET / HTTP/1.1\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    // Shut down while the request is being handled
+    sleep(Duration::from_millis(10));
+    shutdown.shutdown();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 8\r\n\
+        connection: close\r\n\
+        \r\n\
+        Finished"
+    );
+    // New connections are not accepted anymore
+    assert!(TcpStream::connect("localhost:9040").is_err());
+}
@@ -0,0 +1,90 @@
+use std::io::{Read, Write};
+use std::str::from_utf8;
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::sse::{Event, Sse, SseConnection, SseResponse};
+use submillisecond::{router, Application, ServerConfig};
+
+fn sse_server(port: u16, _: Mailbox<()>) {
+    fn events(sse: Sse) -> SseResponse {
+        sse.on_upgrade((), |mut conn: SseConnection, ()| {
+            conn.send(&Event::default().id("1").data("Hello\nworld"))
+                .unwrap();
+        })
+    }
+
+    fn keep_alive(sse: Sse) -> SseResponse {
+        sse.keep_alive(Duration::from_millis(10)).on_upgrade(
+            (),
+            |mut conn: SseConnection<String>, ()| {
+                while let Some(data) = conn.receive() {
+                    conn.send(&Event::default().data(data)).unwrap();
+                }
+            },
+        )
+    }
+
+    Application::new(router! {
+        GET "/events" => events
+        GET "/keep-alive" => keep_alive
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn send_events() {
+    Process::spawn_link(9010, sse_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9010").unwrap();
+    let request = "\
+        GET /events HTTP/1.1\r\n\
+        Accept: text/event-stream\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/event-stream\r\n\
+        cache-control: no-cache\r\n\
+        connection: close\r\n\
+        \r\n\
+        id: 1\n\
+        data: Hello\n\
+        data: world\n\
+        \n"
+    );
+}
+
+#[test]
+fn keep_alive_comments() {
+    Process::spawn_link(9011, sse_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9011").unwrap();
+    let request = "\
+        GET /keep-alive HTTP/1.1\r\n\
+        Accept: text/event-stream\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 256];
+    while !response.ends_with(b":\n\n") {
+        let n = stream.read(&mut buffer).unwrap();
+        assert_ne!(n, 0);
+        response.extend(&buffer[..n]);
+    }
+    let response_str = from_utf8(&response).unwrap();
+    assert!(response_str.starts_with("HTTP/1.1 200 OK\r\n"));
+}
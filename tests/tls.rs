@@ -0,0 +1,55 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TlsStream;
+use lunatic::{sleep, test, Mailbox, Process};
+use submillisecond::{router, Application, RequestContext, ServerConfig};
+
+const CERT: &str = include_str!("certs/localhost.pem");
+const KEY: &str = include_str!("certs/localhost.key");
+
+fn tls_server(port: u16, _: Mailbox<()>) {
+    fn scheme(req: RequestContext) -> &'static str {
+        if req.is_tls() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    Application::new(router! {
+        GET "/" => scheme
+    })
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve_tls(format!("localhost:{port}"), CERT, KEY)
+    .unwrap();
+}
+
+#[test]
+fn serve_tls() {
+    Process::spawn_link(9030, tls_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TlsStream::connect("localhost", 9030, vec![CERT.to_string()]).unwrap();
+    let request = "\
+        GET / HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Connection: close\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        connection: close\r\n\
+        content-length: 5\r\n\
+        \r\n\
+        https"
+    );
+}
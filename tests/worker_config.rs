@@ -0,0 +1,52 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use lunatic::net::TcpStream;
+use lunatic::{sleep, test, Mailbox, Process, ProcessConfig};
+use submillisecond::{router, Application, ServerConfig};
+
+fn limited_worker_server(port: u16, _: Mailbox<()>) {
+    fn allocate() -> String {
+        let data = vec![1u8; 256 * 1024 * 1024];
+        data.iter()
+            .map(|byte| *byte as usize)
+            .sum::<usize>()
+            .to_string()
+    }
+
+    let mut worker_config = ProcessConfig::new().unwrap();
+    worker_config.set_max_memory(32 * 1024 * 1024);
+    Application::new(router! {
+        GET "/" => allocate
+    })
+    .with_worker_config(worker_config)
+    .with_config(ServerConfig {
+        date_header: false,
+        ..Default::default()
+    })
+    .serve(format!("localhost:{port}"))
+    .unwrap();
+}
+
+#[test]
+fn worker_exceeds_memory_limit() {
+    Process::spawn_link(9060, limited_worker_server);
+    // Give enough time to for server to start
+    sleep(Duration::from_millis(10));
+    let mut stream = TcpStream::connect("localhost:9060").unwrap();
+    let request = "\
+        GET / HTTP/1.1\r\n\
+        \r\n"
+        .as_bytes();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 503 Service Unavailable\r\n\
+        content-type: text/plain; charset=utf-8\r\n\
+        content-length: 19\r\n\
+        \r\n\
+        Service Unavailable"
+    );
+}